@@ -1,15 +1,27 @@
+use crate::nvd::Cvss;
+use crate::patch::{FileStat, Impact};
+use crate::release_name;
+use crate::signify::SignatureStatus;
 use chrono::{DateTime, FixedOffset};
 use std::cmp::Ordering;
 
 #[derive(Debug)]
 pub struct AtomEntry {
     pub id: String,
+    pub kind: String,
     pub title: String,
     pub updated: DateTime<FixedOffset>,
     pub link: String,
     pub content: String,
     pub release_version: u16,
     pub iteration_count: usize,
+    pub patch_url: Option<String>,
+    pub signature: Option<SignatureStatus>,
+    pub files: Vec<FileStat>,
+    pub impact: Option<Impact>,
+    pub cves: Vec<String>,
+    pub advisories: Vec<String>,
+    pub cvss: Option<Cvss>,
 }
 
 impl AtomEntry {
@@ -22,4 +34,11 @@ impl AtomEntry {
             o => o,
         }
     }
+
+    /// Whether the entry is of one of `releases` (like `7.4`) and one of `kinds` (like
+    /// `security fix`, case-insensitive). An empty list matches them all.
+    pub(crate) fn matches(&self, releases: &[String], kinds: &[String]) -> bool {
+        (releases.is_empty() || releases.contains(&release_name(self.release_version)))
+            && (kinds.is_empty() || kinds.iter().any(|k| k.eq_ignore_ascii_case(&self.kind)))
+    }
 }
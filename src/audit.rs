@@ -0,0 +1,125 @@
+use crate::{atom_entry::AtomEntry, fetch_entries, parse_release, USAGE};
+use std::{fs, path::Path, process::exit};
+
+/// Name `syspatch` gives to an erratum's patch, e.g. `001_x509` for `v72-p001_x509`.
+pub fn patch_name(entry: &AtomEntry) -> Option<&str> {
+    entry.id[entry.id.find("-").map_or(0, |i| i + 1)..].strip_prefix('p')
+}
+
+fn installed_name(name: &str, version: u16) -> Option<String> {
+    let name = name.trim();
+    let name = name.strip_prefix(&format!("{}-", version)).unwrap_or(name);
+    match name
+        .split('_')
+        .next()
+        .map(|n| n.len() == 3 && n.bytes().all(|b| b.is_ascii_digit()))
+    {
+        Some(true) => Some(name.to_string()),
+        _ => None,
+    }
+}
+
+/// Reads either a `/var/syspatch` directory (`72-001_x509`) or `syspatch -l` output (`001_x509`).
+pub fn read_installed(path: &str, version: u16) -> Result<Vec<String>, String> {
+    let names: Vec<String> = if Path::new(path).is_dir() {
+        fs::read_dir(path)
+            .map_err(|e| format!("Error reading installed patches in {}: {}", path, e))?
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect()
+    } else {
+        fs::read_to_string(path)
+            .map_err(|e| format!("Error reading installed patches in {}: {}", path, e))?
+            .lines()
+            .map(String::from)
+            .collect()
+    };
+    Ok(names
+        .iter()
+        .filter_map(|name| installed_name(name, version))
+        .collect())
+}
+
+pub fn missing_patches<'a>(
+    entries: impl IntoIterator<Item = &'a AtomEntry>,
+    installed: &[String],
+) -> Vec<&'a AtomEntry> {
+    entries
+        .into_iter()
+        .filter(|entry| patch_name(entry).is_some_and(|name| !installed.iter().any(|i| i == name)))
+        .collect()
+}
+
+pub async fn run(args: &[String]) {
+    let (release, path) = match args {
+        [release, path] => (release, path),
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(1);
+        }
+    };
+    let version = match parse_release(release) {
+        Some(version) => version,
+        None => {
+            eprintln!("Invalid release: {}", release);
+            exit(1);
+        }
+    };
+
+    let installed = read_installed(path, version).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+    let entries = fetch_entries(version..=version)
+        .await
+        .unwrap_or_else(|e| e.exit());
+    if entries.is_empty() {
+        eprintln!("No errata found for OpenBSD {}", release);
+        exit(1);
+    }
+
+    let missing = missing_patches(&entries, &installed);
+    println!(
+        "OpenBSD {}: {} installed, {} missing",
+        release,
+        installed.len(),
+        missing.len()
+    );
+    for entry in missing {
+        println!(
+            "{}\t{}\t{}\t{}",
+            patch_name(entry).unwrap_or_default(),
+            entry.kind,
+            entry.updated.format("%Y-%m-%d"),
+            entry.link
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::installed_name;
+
+    #[test]
+    fn reads_syspatch_directory_names() {
+        assert_eq!(
+            installed_name("72-001_x509", 72).as_deref(),
+            Some("001_x509")
+        );
+    }
+
+    #[test]
+    fn reads_syspatch_listing_names() {
+        assert_eq!(
+            installed_name("001_x509\n", 72).as_deref(),
+            Some("001_x509")
+        );
+    }
+
+    #[test]
+    fn ignores_other_names() {
+        assert_eq!(installed_name("73-001_x509", 72), None);
+        assert_eq!(installed_name("README", 72), None);
+        assert_eq!(installed_name("", 72), None);
+    }
+}
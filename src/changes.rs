@@ -0,0 +1,264 @@
+use crate::{
+    atom_entry::AtomEntry, config::FeedConfig, previous_feed::PreviousEntry, ISO_UTC_FORMAT,
+};
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+
+#[derive(Debug, Serialize)]
+pub struct Revision {
+    pub id: String,
+    /// Changed lines of the title and content, prefixed with `-` or `+`.
+    pub diff: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Redating {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// What a run changes in the published feed, by entry id without the `id_prefix`.
+#[derive(Debug, Default, Serialize)]
+pub struct Changes {
+    pub added: Vec<String>,
+    pub revised: Vec<Revision>,
+    pub removed: Vec<String>,
+    pub redated: Vec<Redating>,
+}
+
+fn changed_lines(old: &str, new: &str) -> Vec<String> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .filter_map(|change| {
+            let sign = match change.tag() {
+                ChangeTag::Delete => '-',
+                ChangeTag::Insert => '+',
+                ChangeTag::Equal => return None,
+            };
+            Some(format!("{}{}", sign, change.value().trim_end_matches('\n')))
+        })
+        .collect()
+}
+
+/// Compares the entries of the `previous` feed with the ones about to replace them.
+pub fn diff(feed: &FeedConfig, previous: &[PreviousEntry], entries: &[AtomEntry]) -> Changes {
+    let short_id = |id: &str| {
+        id.strip_prefix(&feed.id_prefix)
+            .and_then(|id| id.strip_prefix('/'))
+            .unwrap_or(id)
+            .to_string()
+    };
+    let mut changes = Changes::default();
+    for entry in entries {
+        let Some(old) = previous.iter().find(|p| short_id(&p.id) == entry.id) else {
+            changes.added.push(entry.id.clone());
+            continue;
+        };
+        let mut diff = changed_lines(&old.title, &entry.title);
+        diff.extend(changed_lines(&old.content, &entry.content));
+        if !diff.is_empty() {
+            changes.revised.push(Revision {
+                id: entry.id.clone(),
+                diff,
+            });
+        }
+        let updated = entry.updated.format(ISO_UTC_FORMAT).to_string();
+        if old.updated != updated {
+            changes.redated.push(Redating {
+                id: entry.id.clone(),
+                from: old.updated.clone(),
+                to: updated,
+            });
+        }
+    }
+    changes.removed = previous
+        .iter()
+        .map(|p| short_id(&p.id))
+        .filter(|id| !entries.iter().any(|e| &e.id == id))
+        .collect();
+    changes
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.revised.is_empty()
+            && self.removed.is_empty()
+            && self.redated.is_empty()
+    }
+
+    /// One line with the counts.
+    fn headline(&self) -> String {
+        format!(
+            "{} added, {} revised, {} removed, {} redated",
+            self.added.len(),
+            self.revised.len(),
+            self.removed.len(),
+            self.redated.len()
+        )
+    }
+
+    pub fn to_text(&self) -> String {
+        if self.is_empty() {
+            return String::from("No entry changed");
+        }
+        let mut text = self.headline() + "\n";
+        let mut section = |title: &str, lines: Vec<String>| {
+            if !lines.is_empty() {
+                text += &format!("\n{}:\n", title);
+                for line in lines {
+                    text += &format!("    {}\n", line);
+                }
+            }
+        };
+        section("Added", self.added.clone());
+        section(
+            "Revised",
+            self.revised
+                .iter()
+                .flat_map(|r| {
+                    std::iter::once(r.id.clone()).chain(r.diff.iter().map(|l| format!("    {}", l)))
+                })
+                .collect(),
+        );
+        section("Removed", self.removed.clone());
+        section(
+            "Redated",
+            self.redated
+                .iter()
+                .map(|r| format!("{}: {} -> {}", r.id, r.from, r.to))
+                .collect(),
+        );
+        text.trim_end().to_string()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+    use crate::{
+        atom_entry::AtomEntry, config::FeedConfig, previous_feed::PreviousEntry, ISO_UTC_FORMAT,
+    };
+    use chrono::DateTime;
+
+    fn entry(id: &str, title: &str, updated: &str, content: &str) -> AtomEntry {
+        AtomEntry {
+            id: id.to_string(),
+            kind: String::from("security fix"),
+            title: title.to_string(),
+            updated: DateTime::parse_from_rfc3339(updated).unwrap(),
+            link: String::new(),
+            content: content.to_string(),
+            release_version: 74,
+            iteration_count: 0,
+            patch_url: None,
+            signature: None,
+            files: Vec::new(),
+            impact: None,
+            cves: Vec::new(),
+            advisories: Vec::new(),
+            cvss: None,
+        }
+    }
+
+    fn previous(entry: &AtomEntry) -> PreviousEntry {
+        PreviousEntry {
+            id: format!("{}/{}", FeedConfig::default().id_prefix, entry.id),
+            title: entry.title.clone(),
+            updated: entry.updated.format(ISO_UTC_FORMAT).to_string(),
+            content: entry.content.clone(),
+            link: String::new(),
+            advisories: Vec::new(),
+            impact: None,
+            cves: Vec::new(),
+        }
+    }
+
+    const DATE: &str = "2024-01-01T00:00:00Z";
+
+    #[test]
+    fn reports_each_kind_of_change() {
+        let kept = entry("v74-p001_a", "001: security fix", DATE, "<p>same</p>");
+        let revised = entry("v74-p002_b", "002: security fix", DATE, "line 1\nline 2\n");
+        let redated = entry("v74-p003_c", "003: reliability fix", DATE, "");
+        let removed = entry("v74-p004_d", "004: security fix", DATE, "");
+        let old: Vec<PreviousEntry> = [&kept, &revised, &redated, &removed]
+            .into_iter()
+            .map(previous)
+            .collect();
+        let entries = [
+            entry("v74-p005_e", "005: security fix", DATE, ""),
+            kept,
+            entry("v74-p002_b", "002: security fix", DATE, "line 1\nline 3\n"),
+            entry(
+                "v74-p003_c",
+                "003: reliability fix",
+                "2024-01-02T00:00:00Z",
+                "",
+            ),
+        ];
+
+        let changes = diff(&FeedConfig::default(), &old, &entries);
+        assert_eq!(changes.added, ["v74-p005_e"]);
+        assert_eq!(changes.revised.len(), 1);
+        assert_eq!(changes.revised[0].id, "v74-p002_b");
+        assert_eq!(changes.revised[0].diff, ["-line 2", "+line 3"]);
+        assert_eq!(changes.removed, ["v74-p004_d"]);
+        assert_eq!(changes.redated.len(), 1);
+        assert_eq!(changes.redated[0].from, DATE);
+        assert_eq!(changes.redated[0].to, "2024-01-02T00:00:00Z");
+
+        assert_eq!(
+            changes.to_text(),
+            concat!(
+                "1 added, 1 revised, 1 removed, 1 redated\n",
+                "\n",
+                "Added:\n",
+                "    v74-p005_e\n",
+                "\n",
+                "Revised:\n",
+                "    v74-p002_b\n",
+                "        -line 2\n",
+                "        +line 3\n",
+                "\n",
+                "Removed:\n",
+                "    v74-p004_d\n",
+                "\n",
+                "Redated:\n",
+                "    v74-p003_c: 2024-01-01T00:00:00Z -> 2024-01-02T00:00:00Z"
+            )
+        );
+        assert_eq!(
+            changes.to_json(),
+            concat!(
+                r#"{"added":["v74-p005_e"],"#,
+                r#""revised":[{"id":"v74-p002_b","diff":["-line 2","+line 3"]}],"#,
+                r#""removed":["v74-p004_d"],"#,
+                r#""redated":[{"id":"v74-p003_c","from":"2024-01-01T00:00:00Z","to":"2024-01-02T00:00:00Z"}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn strips_only_the_configured_prefix() {
+        let kept = entry("v74-p001_a", "001: security fix", DATE, "");
+        let mut foreign = previous(&kept);
+        foreign.id = String::from("tag:example.org,2024:other/v74-p001_a");
+        let changes = diff(&FeedConfig::default(), &[foreign], &[kept]);
+        assert_eq!(changes.added, ["v74-p001_a"]);
+        assert_eq!(changes.removed, ["tag:example.org,2024:other/v74-p001_a"]);
+    }
+
+    #[test]
+    fn reports_no_change() {
+        let kept = entry("v74-p001_a", "001: security fix", DATE, "<p>same</p>");
+        let changes = diff(&FeedConfig::default(), &[previous(&kept)], &[kept]);
+        assert!(changes.is_empty());
+        assert_eq!(changes.to_text(), "No entry changed");
+    }
+}
@@ -0,0 +1,547 @@
+use crate::{
+    FEED_AUTHOR_NAME, FEED_AUTHOR_URI, FEED_ID_PREFIX, FEED_LINK, FEED_LINK_REL, FEED_TITLE,
+    FEED_UUID, GITHUB_COMMIT_EMAIL, GITHUB_COMMIT_MESSAGE, GITHUB_COMMIT_URL, GITHUB_TOKEN_PATH,
+};
+use serde::Deserialize;
+use std::{fs, io::ErrorKind, process::exit};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub signify: SignifyConfig,
+    pub patches: PatchesConfig,
+    pub nvd: NvdConfig,
+    pub site: SiteConfig,
+    pub feed: FeedConfig,
+    pub templates_dir: Option<String>,
+    pub outputs: Vec<OutputConfig>,
+    pub guard: GuardConfig,
+    pub publisher: PublisherKind,
+    /// Subject of the commit, the body lists the changed entries.
+    pub commit_message: String,
+    pub changes_format: ChangesFormat,
+    /// Times the feed is merged with a concurrently published one and sent again.
+    pub conflict_retries: u32,
+    pub github: GitHubConfig,
+    pub gitea: GiteaConfig,
+    pub gitlab: GitLabConfig,
+    pub git: GitConfig,
+    pub s3: S3Config,
+    pub daemon: DaemonConfig,
+    pub serve: ServeConfig,
+    pub email: EmailConfig,
+    pub webhooks: Vec<WebhookConfig>,
+    pub mastodon: MastodonConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignifyConfig {
+    pub enabled: bool,
+    pub keys_dir: String,
+    pub strict: bool,
+}
+
+impl Default for SignifyConfig {
+    fn default() -> SignifyConfig {
+        SignifyConfig {
+            enabled: false,
+            keys_dir: String::from("/etc/signify"),
+            strict: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PatchesConfig {
+    pub diffstat: bool,
+    pub classify: bool,
+    pub cache_dir: String,
+}
+
+impl Default for PatchesConfig {
+    fn default() -> PatchesConfig {
+        PatchesConfig {
+            diffstat: false,
+            classify: false,
+            cache_dir: String::from("/var/cache/syspatch-feed"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NvdConfig {
+    pub feed: Option<String>,
+    pub min_severity: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    pub output_dir: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeedConfig {
+    pub title: String,
+    pub link: String,
+    pub link_rel: String,
+    pub author_name: String,
+    pub author_uri: String,
+    pub id: String,
+    pub id_prefix: String,
+    /// WebSub hub announced in the feed and notified after publishing it.
+    pub hub: Option<String>,
+}
+
+impl Default for FeedConfig {
+    fn default() -> FeedConfig {
+        FeedConfig {
+            title: String::from(FEED_TITLE),
+            link: String::from(FEED_LINK),
+            link_rel: String::from(FEED_LINK_REL),
+            author_name: String::from(FEED_AUTHOR_NAME),
+            author_uri: String::from(FEED_AUTHOR_URI),
+            id: String::from(FEED_UUID),
+            id_prefix: String::from(FEED_ID_PREFIX),
+            hub: None,
+        }
+    }
+}
+
+/// Extra file rendered from a user template with the same variables as the feed.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    pub template: String,
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GuardConfig {
+    pub max_removed_percent: f32,
+}
+
+impl Default for GuardConfig {
+    fn default() -> GuardConfig {
+        GuardConfig {
+            max_removed_percent: 10.,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PublisherKind {
+    #[default]
+    GitHub,
+    Gitea,
+    GitLab,
+    Git,
+    S3,
+}
+
+/// How the changes of a run are printed.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangesFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Where a token or secret comes from, like `{"env": "GITHUB_TOKEN"}`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Credential {
+    Env(String),
+    /// Only the owner and group may access the file.
+    File(String),
+    /// Program and arguments printing the secret, like a git credential helper.
+    Command(Vec<String>),
+    GithubApp(GitHubAppConfig),
+}
+
+/// GitHub App whose installation token is requested with a JWT signed by its private key.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GitHubAppConfig {
+    pub app_id: u64,
+    pub installation_id: u64,
+    pub private_key_path: String,
+    #[serde(default = "github_api_url")]
+    pub api_url: String,
+}
+
+fn github_api_url() -> String {
+    String::from("https://api.github.com")
+}
+
+fn default_token() -> Credential {
+    Credential::File(String::from(GITHUB_TOKEN_PATH))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitHubConfig {
+    pub contents_url: String,
+    pub token: Credential,
+}
+
+impl Default for GitHubConfig {
+    fn default() -> GitHubConfig {
+        GitHubConfig {
+            contents_url: String::from(GITHUB_COMMIT_URL),
+            token: default_token(),
+        }
+    }
+}
+
+/// Repository updated through the Gitea contents API (`/api/v1/repos/{owner}/{repo}/contents`).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GiteaConfig {
+    pub api_url: String,
+    pub owner: String,
+    pub repo: String,
+    pub branch: String,
+    pub file: String,
+    pub author_name: String,
+    pub author_email: String,
+    /// Required, so the GitHub token never goes to another host.
+    pub token: Option<Credential>,
+}
+
+impl Default for GiteaConfig {
+    fn default() -> GiteaConfig {
+        GiteaConfig {
+            api_url: String::from("https://gitea.com/api/v1"),
+            owner: String::new(),
+            repo: String::new(),
+            branch: String::from("main"),
+            file: String::from("pub/atom.xml"),
+            author_name: String::from(FEED_AUTHOR_NAME),
+            author_email: String::from(GITHUB_COMMIT_EMAIL),
+            token: None,
+        }
+    }
+}
+
+/// Project updated through the GitLab Repository Files API. `project` is its numeric id or
+/// its full path, like `group/project`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitLabConfig {
+    pub api_url: String,
+    pub project: String,
+    pub branch: String,
+    pub file: String,
+    pub author_name: String,
+    pub author_email: String,
+    /// Required, so the GitHub token never goes to another host.
+    pub token: Option<Credential>,
+}
+
+impl Default for GitLabConfig {
+    fn default() -> GitLabConfig {
+        GitLabConfig {
+            api_url: String::from("https://gitlab.com/api/v4"),
+            project: String::new(),
+            branch: String::from("main"),
+            file: String::from("pub/atom.xml"),
+            author_name: String::from(FEED_AUTHOR_NAME),
+            author_email: String::from(GITHUB_COMMIT_EMAIL),
+            token: None,
+        }
+    }
+}
+
+/// Local working copy the feed is committed to by the `git` publisher.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitConfig {
+    pub repo_dir: String,
+    pub file: String,
+    pub branch: String,
+    pub author_name: String,
+    pub author_email: String,
+    pub remote: Option<String>,
+    pub push: bool,
+}
+
+impl Default for GitConfig {
+    fn default() -> GitConfig {
+        GitConfig {
+            repo_dir: String::from("."),
+            file: String::from("pub/atom.xml"),
+            branch: String::from("main"),
+            author_name: String::from(FEED_AUTHOR_NAME),
+            author_email: String::from(GITHUB_COMMIT_EMAIL),
+            remote: None,
+            push: false,
+        }
+    }
+}
+
+/// Bucket of an S3-compatible object storage, addressed path-style (`{endpoint}/{bucket}/{key}`).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub key: String,
+    pub access_key_id: String,
+    /// Required, so the GitHub token never ends up as the signing secret.
+    pub secret_key: Option<Credential>,
+    pub cache_control: String,
+}
+
+impl Default for S3Config {
+    fn default() -> S3Config {
+        S3Config {
+            endpoint: String::from("http://localhost:9000"),
+            region: String::from("us-east-1"),
+            bucket: String::new(),
+            key: String::from("atom.xml"),
+            access_key_id: String::new(),
+            secret_key: None,
+            cache_control: String::from("public, max-age=300"),
+        }
+    }
+}
+
+/// Schedule of the `daemon` command: a run every `interval_secs`, delayed up to `jitter_secs`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    pub interval_secs: u64,
+    pub jitter_secs: u64,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> DaemonConfig {
+        DaemonConfig {
+            interval_secs: 3600,
+            jitter_secs: 300,
+        }
+    }
+}
+
+/// HTTP server of the `serve` command. The extra outputs are served by their file name.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServeConfig {
+    pub listen: String,
+    pub feed_path: String,
+    /// Where the built-in WebSub hub takes subscriptions, disabled when unset.
+    pub hub_path: Option<String>,
+}
+
+impl Default for ServeConfig {
+    fn default() -> ServeConfig {
+        ServeConfig {
+            listen: String::from("127.0.0.1:8080"),
+            feed_path: String::from("/atom.xml"),
+            hub_path: None,
+        }
+    }
+}
+
+/// SMTP server mailing the errata that are new in a published feed, disabled when `host` is
+/// unset. Without `starttls` the connection is plain text, only meant for a local relay.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailConfig {
+    pub host: Option<String>,
+    pub port: u16,
+    pub starttls: bool,
+    pub username: Option<String>,
+    pub password: Option<Credential>,
+    pub from: String,
+    /// One message per recipient group listing every new erratum, instead of one per erratum.
+    pub digest: bool,
+    pub recipients: Vec<RecipientsConfig>,
+}
+
+impl Default for EmailConfig {
+    fn default() -> EmailConfig {
+        EmailConfig {
+            host: None,
+            port: 587,
+            starttls: true,
+            username: None,
+            password: None,
+            from: String::from("syspatch-feed@localhost"),
+            digest: false,
+            recipients: Vec::new(),
+        }
+    }
+}
+
+/// Addresses mailed the new errata of the given releases (like `7.4`) and kinds (like
+/// `security fix`). An empty list matches them all.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecipientsConfig {
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub releases: Vec<String>,
+    #[serde(default)]
+    pub kinds: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookKind {
+    /// Slack incoming webhook, or any chat accepting its `{"text": ...}` payload.
+    Slack,
+    /// Room of a Matrix homeserver, posted to through the client-server API.
+    Matrix,
+    /// Body rendered from `template`, a JSON template in `templates_dir`.
+    Json,
+}
+
+/// Chat the new errata are posted to, one message each, when they match `releases` and `kinds`.
+/// For Matrix, `url` is the homeserver and `token` the access token of the posting user; for
+/// the other kinds `url` is the webhook and `token`, if any, is sent as a bearer token.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    #[serde(rename = "type")]
+    pub kind: WebhookKind,
+    pub url: String,
+    #[serde(default)]
+    pub room_id: Option<String>,
+    #[serde(default)]
+    pub token: Option<Credential>,
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub releases: Vec<String>,
+    #[serde(default)]
+    pub kinds: Vec<String>,
+    /// Times a request is sent again after a network error, a 429 or a 5xx response.
+    #[serde(default = "webhook_retries")]
+    pub retries: u32,
+}
+
+fn webhook_retries() -> u32 {
+    3
+}
+
+/// Mastodon-compatible account the new errata are posted to, disabled when `instance` is unset.
+/// Ids of the entries already announced are kept in `state_file`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MastodonConfig {
+    pub instance: Option<String>,
+    pub token: Credential,
+    pub state_file: String,
+    /// Length limit of a status on the instance, including the content warning.
+    pub max_chars: usize,
+    pub visibility: String,
+    /// Hides the text of security fixes behind a content warning naming the release.
+    pub content_warning: bool,
+    pub releases: Vec<String>,
+    pub kinds: Vec<String>,
+}
+
+impl Default for MastodonConfig {
+    fn default() -> MastodonConfig {
+        MastodonConfig {
+            instance: None,
+            token: Credential::File(String::from("/etc/syspatch-feed-mastodon")),
+            state_file: String::from("/var/db/syspatch-feed-mastodon.json"),
+            max_chars: 500,
+            visibility: String::from("public"),
+            content_warning: true,
+            releases: Vec::new(),
+            kinds: Vec::new(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            signify: SignifyConfig::default(),
+            patches: PatchesConfig::default(),
+            nvd: NvdConfig::default(),
+            site: SiteConfig::default(),
+            feed: FeedConfig::default(),
+            templates_dir: None,
+            outputs: Vec::new(),
+            guard: GuardConfig::default(),
+            publisher: PublisherKind::default(),
+            commit_message: String::from(GITHUB_COMMIT_MESSAGE),
+            changes_format: ChangesFormat::default(),
+            conflict_retries: 3,
+            github: GitHubConfig::default(),
+            gitea: GiteaConfig::default(),
+            gitlab: GitLabConfig::default(),
+            git: GitConfig::default(),
+            s3: S3Config::default(),
+            daemon: DaemonConfig::default(),
+            serve: ServeConfig::default(),
+            email: EmailConfig::default(),
+            webhooks: Vec::new(),
+            mastodon: MastodonConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &str) -> Config {
+        match Config::try_load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+    }
+
+    /// Settings serde can't tell are missing or at odds.
+    fn check(&self) -> Result<(), String> {
+        let missing = match self.publisher {
+            PublisherKind::Gitea if self.gitea.token.is_none() => Some("gitea.token"),
+            PublisherKind::Gitea if self.gitea.owner.is_empty() => Some("gitea.owner"),
+            PublisherKind::Gitea if self.gitea.repo.is_empty() => Some("gitea.repo"),
+            PublisherKind::GitLab if self.gitlab.token.is_none() => Some("gitlab.token"),
+            PublisherKind::GitLab if self.gitlab.project.is_empty() => Some("gitlab.project"),
+            PublisherKind::S3 if self.s3.secret_key.is_none() => Some("s3.secret_key"),
+            _ => None,
+        };
+        if let Some(setting) = missing {
+            return Err(format!(
+                "{} is required by the {:?} publisher",
+                setting, self.publisher
+            ));
+        }
+        if self.git.push && self.git.remote.is_none() {
+            return Err(String::from("git.push needs a git.remote"));
+        }
+        Ok(())
+    }
+
+    /// A missing file is not an error: every optional step stays disabled.
+    pub fn try_load(path: &str) -> Result<Config, String> {
+        match fs::read_to_string(path) {
+            Ok(json) => {
+                let config: Config = serde_json::from_str(&json)
+                    .map_err(|e| format!("Config Error in {}: {}", path, e))?;
+                config
+                    .check()
+                    .map_err(|e| format!("Config Error in {}: {}", path, e))?;
+                Ok(config)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("Error reading config file {}: {}", path, e)),
+        }
+    }
+}
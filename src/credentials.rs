@@ -0,0 +1,98 @@
+use crate::{
+    config::{Credential, GitHubAppConfig},
+    html::github_headers,
+    secret_file,
+};
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::Serialize;
+use serde_json::Value;
+use std::env;
+use tokio::process::Command;
+
+/// Reads the secret, without surrounding whitespace.
+pub async fn resolve(credential: &Credential) -> Result<String, String> {
+    let secret = match credential {
+        Credential::Env(var) => {
+            env::var(var).map_err(|e| format!("environment variable {}: {}", var, e))?
+        }
+        Credential::File(path) => read_file(path)?,
+        Credential::Command(command) => run_command(command).await?,
+        Credential::GithubApp(app) => installation_token(app).await?,
+    };
+    match secret.trim() {
+        "" => Err(String::from("empty secret")),
+        secret => Ok(secret.to_string()),
+    }
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    secret_file::read(path).map_err(|violations| violations.join("; "))
+}
+
+async fn run_command(command: &[String]) -> Result<String, String> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(String::from("empty credential command"));
+    };
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("cannot run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout).map_err(|e| format!("{} output: {}", program, e))
+}
+
+#[derive(Serialize)]
+struct AppClaims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+/// Installation tokens last an hour, one is requested on every run.
+async fn installation_token(app: &GitHubAppConfig) -> Result<String, String> {
+    let key = EncodingKey::from_rsa_pem(read_file(&app.private_key_path)?.as_bytes())
+        .map_err(|e| format!("invalid private key {}: {}", app.private_key_path, e))?;
+    //Backdated against clock drift, GitHub refuses JWTs valid for more than 10 minutes
+    let now = Utc::now().timestamp();
+    let claims = AppClaims {
+        iat: now - 60,
+        exp: now + 9 * 60,
+        iss: app.app_id.to_string(),
+    };
+    let jwt = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &key)
+        .map_err(|e| format!("cannot sign GitHub App JWT: {}", e))?;
+
+    let url = format!(
+        "{}/app/installations/{}/access_tokens",
+        app.api_url.trim_end_matches('/'),
+        app.installation_id
+    );
+    let res = reqwest::Client::new()
+        .post(&url)
+        .headers(github_headers())
+        .bearer_auth(jwt)
+        .send()
+        .await
+        .map_err(|e| format!("request to {} failed: {}", url, e))?;
+    let status = res.status().as_u16();
+    let body: Value = res
+        .json()
+        .await
+        .map_err(|e| format!("error parsing response: {}", e))?;
+    match (status, body["token"].as_str()) {
+        (201, Some(token)) => Ok(token.to_string()),
+        _ => Err(format!(
+            "GitHub Server Error: {}, Response Body: {}",
+            status, body
+        )),
+    }
+}
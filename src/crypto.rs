@@ -0,0 +1,20 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    //HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC key");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// `len` bytes from the operating system's random source, hex-encoded.
+pub fn random_hex(len: usize) -> Result<String, String> {
+    let mut bytes = vec![0; len];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("no random source: {}", e))?;
+    Ok(hex(&bytes))
+}
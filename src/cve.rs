@@ -0,0 +1,123 @@
+use crate::{
+    atom_entry::AtomEntry, audit::patch_name, fetch_entries, get_latest_version, parse_release,
+    release_name, USAGE,
+};
+use regex::Regex;
+use std::process::exit;
+
+/// Domain whose links, subdomains included, are part of the errata themselves rather than
+/// upstream advisories.
+const OPENBSD_DOMAIN: &str = "openbsd.org";
+
+pub struct CveRegexes {
+    cve: Regex,
+    href: Regex,
+}
+
+impl CveRegexes {
+    pub fn new() -> CveRegexes {
+        match (
+            Regex::new("CVE-\\d{4}-\\d{4,}"),
+            Regex::new("href=\"(https?://([^/\"]+)[^\"]*)\""),
+        ) {
+            (Ok(cve), Ok(href)) => CveRegexes { cve, href },
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("Wrong regex: {}", e);
+                exit(1);
+            }
+        }
+    }
+}
+
+fn is_openbsd_host(authority: &str) -> bool {
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    let host = host.split(':').next().unwrap_or(host).to_ascii_lowercase();
+    host == OPENBSD_DOMAIN
+        || host
+            .strip_suffix(OPENBSD_DOMAIN)
+            .is_some_and(|sub| sub.ends_with('.'))
+}
+
+/// Fills the CVE ids and upstream advisory links mentioned in the erratum text.
+pub fn extract_references(entry: &mut AtomEntry, regexes: &CveRegexes) {
+    for m in regexes.cve.find_iter(&entry.content) {
+        if !entry.cves.iter().any(|cve| cve == m.as_str()) {
+            entry.cves.push(m.as_str().to_string());
+        }
+    }
+    for c in regexes.href.captures_iter(&entry.content) {
+        let link = html_escape::decode_html_entities(&c[1]).to_string();
+        if !is_openbsd_host(&c[2]) && !entry.advisories.contains(&link) {
+            entry.advisories.push(link);
+        }
+    }
+}
+
+pub async fn run(args: &[String]) {
+    let (cve, release) = match args {
+        [cve] => (cve.to_uppercase(), None),
+        [cve, release] => (cve.to_uppercase(), Some(release)),
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(1);
+        }
+    };
+    let versions = match release.map(|r| (r, parse_release(r))) {
+        Some((_, Some(version))) => version..=version,
+        Some((release, None)) => {
+            eprintln!("Invalid release: {}", release);
+            exit(1);
+        }
+        None => {
+            let latest_version = get_latest_version().await.unwrap_or_else(|e| e.exit());
+            latest_version - 2..=latest_version
+        }
+    };
+
+    let entries = fetch_entries(versions.clone())
+        .await
+        .unwrap_or_else(|e| e.exit());
+    for version in versions {
+        let fixes: Vec<&AtomEntry> = entries
+            .iter()
+            .filter(|e| e.release_version == version && e.cves.contains(&cve))
+            .collect();
+        if fixes.is_empty() {
+            println!(
+                "{} is not mentioned in the OpenBSD {} errata",
+                cve,
+                release_name(version)
+            );
+        }
+        for fix in fixes {
+            println!(
+                "{} is fixed in OpenBSD {} by {} ({}, {}): {}",
+                cve,
+                release_name(version),
+                patch_name(fix).unwrap_or("an erratum without patch"),
+                fix.kind,
+                fix.updated.format("%Y-%m-%d"),
+                fix.link
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_openbsd_host;
+
+    #[test]
+    fn matches_openbsd_subdomains() {
+        assert!(is_openbsd_host("openbsd.org"));
+        assert!(is_openbsd_host("cvsweb.openbsd.org"));
+        assert!(is_openbsd_host("WWW.OpenBSD.org:443"));
+    }
+
+    #[test]
+    fn rejects_lookalike_hosts() {
+        assert!(!is_openbsd_host("notopenbsd.org"));
+        assert!(!is_openbsd_host("openbsd.org.example.com"));
+        assert!(!is_openbsd_host("openbsd.org@example.com"));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{config::Config, publish_configured, RunError, CONFIG_PATH};
+use std::{
+    future::Future,
+    process::exit,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    signal::unix::{signal, Signal, SignalKind},
+    time::{sleep_until, Instant},
+};
+
+/// Spreads hosts sharing an interval, it doesn't need a proper random number generator.
+fn jitter(max_secs: u64) -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos() as u64);
+    match max_secs {
+        0 => Duration::ZERO,
+        max => Duration::from_millis(nanos % (max * 1000)),
+    }
+}
+
+fn listen(kind: SignalKind) -> Signal {
+    match signal(kind) {
+        Ok(signal) => signal,
+        Err(e) => {
+            eprintln!("Error listening for signals: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn check(config: &Config) -> Result<(), String> {
+    match config.daemon.interval_secs {
+        0 => Err(String::from(
+            "Config Error: daemon.interval_secs must be positive",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// A broken file keeps the configuration in use.
+fn reload(config: &mut Arc<Config>) {
+    match Config::try_load(CONFIG_PATH).and_then(|new| check(&new).map(|_| new)) {
+        Ok(new) => {
+            *config = Arc::new(new);
+            println!("Reloaded {}", CONFIG_PATH);
+        }
+        Err(e) => eprintln!("{}, keeping the previous configuration", e),
+    }
+}
+
+/// Publishes the feed on the configured schedule.
+pub async fn run(config: Config) {
+    schedule(
+        config,
+        |config| async move { publish_configured(&config).await },
+    )
+    .await
+}
+
+/// Runs `job` forever, one run at a time. SIGHUP reloads the configuration before the next
+/// run, SIGTERM and SIGINT let the current run finish and return.
+pub async fn schedule<F, R>(config: Config, job: F)
+where
+    F: Fn(Arc<Config>) -> R,
+    R: Future<Output = Result<(), RunError>>,
+{
+    let mut config = Arc::new(config);
+    let mut hangup = listen(SignalKind::hangup());
+    let mut terminate = listen(SignalKind::terminate());
+    let mut interrupt = listen(SignalKind::interrupt());
+
+    if let Err(e) = check(&config) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+    loop {
+        let started = Instant::now();
+        let (mut reload_pending, mut stop) = (false, false);
+        let result = {
+            let run = job(config.clone());
+            tokio::pin!(run);
+            loop {
+                tokio::select! {
+                    result = &mut run => break result,
+                    _ = hangup.recv() => {
+                        println!("SIGHUP received, reloading after the current run");
+                        reload_pending = true;
+                    }
+                    _ = terminate.recv() => stop = true,
+                    _ = interrupt.recv() => stop = true,
+                }
+                if stop {
+                    println!("Stopping after the current run");
+                }
+            }
+        };
+        match result {
+            Ok(()) => println!("Run finished in {}s", started.elapsed().as_secs()),
+            Err(e) => eprintln!("Run failed (exit code {}): {}", e.code, e),
+        }
+        if stop {
+            return;
+        }
+        if reload_pending {
+            reload(&mut config);
+        }
+
+        let interval = Duration::from_secs(config.daemon.interval_secs);
+        let next = started + interval + jitter(config.daemon.jitter_secs);
+        //Runs never overlap, a late one is followed right away by the next
+        if Instant::now() >= started + interval {
+            eprintln!("Run took longer than the interval, starting the next one");
+        }
+        loop {
+            tokio::select! {
+                _ = sleep_until(next) => break,
+                _ = hangup.recv() => reload(&mut config),
+                _ = terminate.recv() => return,
+                _ = interrupt.recv() => return,
+            }
+        }
+    }
+}
@@ -1,11 +1,10 @@
 use html5ever::tendril::StrTendril;
 use markup5ever_rcdom::{Handle, RcDom};
 use std::collections::HashMap;
-use std::process::exit;
 
-use crate::html::{http_get, parse_html};
+use crate::html::{http_get, parse_html, HttpError};
 use crate::traverse_dom::{TraverseAttrs, TraverseDom};
-use crate::PATCHES_URL;
+use crate::{missing_tag, RunError, PATCHES_URL};
 
 #[derive(Debug)]
 pub struct DateIndex {
@@ -51,23 +50,14 @@ impl DateIndex {
         }
     }
 
-    fn get_archs(dom: &RcDom) -> Vec<String> {
-        match match match &dom.document.first_child_by_name("html") {
-            Some(html) => html,
-            None => {
-                eprintln!("Document Error: Missing <html> tag");
-                exit(1);
-            }
-        }
-        .first_child_by_name("body")
-        {
-            Some(body) => body,
-            None => {
-                eprintln!("Document Error: Missing <body> tag");
-                exit(1);
-            }
-        }
-        .first_child_by_name("pre")
+    fn get_archs(dom: &RcDom) -> Result<Vec<String>, RunError> {
+        Ok(match dom
+            .document
+            .first_child_by_name("html")
+            .ok_or_else(|| missing_tag("html"))?
+            .first_child_by_name("body")
+            .ok_or_else(|| missing_tag("body"))?
+            .first_child_by_name("pre")
         {
             Some(pre) => pre.children_by_name("a"),
             None => {
@@ -80,10 +70,13 @@ impl DateIndex {
         .filter(|href| href.ends_with("/"))
         .filter(|href| !href.starts_with("."))
         .map(|href| href.to_string())
-        .collect()
+        .collect())
     }
 
-    pub async fn lazy_load(&mut self, version: u16) -> &mut Option<HashMap<String, StrTendril>> {
+    pub async fn lazy_load(
+        &mut self,
+        version: u16,
+    ) -> Result<&mut Option<HashMap<String, StrTendril>>, RunError> {
         let mut load = false;
         match self.idx {
             None => {
@@ -103,21 +96,23 @@ impl DateIndex {
 
             let mut arch_html = match http_get(&arch_url, false).await {
                 Ok((html, _)) => html,
-                Err(_) => return &mut self.idx,
+                Err(HttpError::NotFound) => return Ok(&mut self.idx),
+                Err(e) => return Err(e.into()),
             };
             let arch_dom = parse_html(&mut arch_html);
-            let archs = Self::get_archs(&arch_dom);
+            let archs = Self::get_archs(&arch_dom)?;
 
             for arch in archs {
                 println!("Arch: {}", arch.as_str());
                 let mut arch_html = match http_get(&(arch_url.clone() + &arch), false).await {
                     Ok((html, _)) => html,
-                    Err(_) => break,
+                    Err(HttpError::NotFound) => break,
+                    Err(e) => return Err(e.into()),
                 };
                 let arch_dom = parse_html(&mut arch_html);
                 self.fill_date_idx(&arch_dom);
             }
         }
-        &mut self.idx
+        Ok(&mut self.idx)
     }
 }
@@ -0,0 +1,114 @@
+use crate::{
+    atom_entry::AtomEntry,
+    config::{Config, EmailConfig},
+    credentials,
+    templates::{self, EMAIL_HTML_TEMPLATE, EMAIL_TEXT_TEMPLATE},
+    ISO_UTC_FORMAT,
+};
+use chrono::Utc;
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde_json::Value;
+use tera::Tera;
+
+type Mailer = AsyncSmtpTransport<Tokio1Executor>;
+
+async fn mailer(config: &EmailConfig, host: &str) -> Result<Mailer, String> {
+    let mut builder = match config.starttls {
+        true => Mailer::starttls_relay(host).map_err(|e| e.to_string())?,
+        false => Mailer::builder_dangerous(host),
+    }
+    .port(config.port);
+    if let Some(ref username) = config.username {
+        let password = match config.password {
+            Some(ref password) => credentials::resolve(password).await?,
+            None => String::new(),
+        };
+        builder = builder.credentials(Credentials::new(username.clone(), password));
+    }
+    Ok(builder.build())
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, String> {
+    address
+        .parse()
+        .map_err(|e| format!("invalid address {:?}: {}", address, e))
+}
+
+fn message(
+    config: &Config,
+    tera: &Tera,
+    entries: &[&AtomEntry],
+    to: &[String],
+) -> Result<Message, String> {
+    let subject = match entries {
+        [entry] => entry.title.clone(),
+        _ => format!("{}: {} new errata", config.feed.title, entries.len()),
+    };
+    let updated = Utc::now().format(ISO_UTC_FORMAT).to_string();
+    let mut context = templates::feed_context(&config.feed, &updated, &[]);
+    context["entries"] = Value::from_iter(entries.iter().map(|e| templates::entry_context(e)));
+    let text = templates::render(tera, EMAIL_TEXT_TEMPLATE, &context)?;
+    let html = templates::render(tera, EMAIL_HTML_TEMPLATE, &context)?;
+
+    let mut builder = Message::builder()
+        .from(mailbox(&config.email.from)?)
+        .subject(subject);
+    for address in to {
+        builder = builder.to(mailbox(address)?);
+    }
+    builder
+        .multipart(MultiPart::alternative_plain_html(text, html))
+        .map_err(|e| e.to_string())
+}
+
+async fn send(config: &Config, tera: &Tera, entries: &[&AtomEntry]) -> Result<(), String> {
+    let Some(ref host) = config.email.host else {
+        return Ok(());
+    };
+    let mailer = mailer(&config.email, host).await?;
+    for recipients in &config.email.recipients {
+        let matching: Vec<&AtomEntry> = entries
+            .iter()
+            .copied()
+            .filter(|e| e.matches(&recipients.releases, &recipients.kinds))
+            .collect();
+        let messages: Vec<&[&AtomEntry]> = match config.email.digest {
+            true if !matching.is_empty() => vec![&matching],
+            true => Vec::new(),
+            false => matching.chunks(1).collect(),
+        };
+        //A failing group doesn't keep the next ones from getting theirs
+        for entries in messages {
+            let sent = match message(config, tera, entries, &recipients.to) {
+                Ok(message) => mailer.send(message).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            };
+            match sent {
+                Ok(_) => println!(
+                    "Mailed {} new errata to {}",
+                    entries.len(),
+                    recipients.to.join(", ")
+                ),
+                Err(e) => {
+                    eprintln!("Email Error: {}: {}", recipients.to.join(", "), e);
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Mails the new entries to every recipient group they match.
+pub async fn notify(config: &Config, tera: &Tera, entries: &[&AtomEntry]) {
+    if entries.is_empty() {
+        return;
+    }
+    if let Err(e) = send(config, tera, entries).await {
+        eprintln!("Email Error: {}", e);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{
+    atom_entry::AtomEntry,
+    audit::{missing_patches, patch_name, read_installed},
+    fetch_entries, parse_release, USAGE,
+};
+use chrono::Utc;
+use serde_json::json;
+use std::{fs, process::exit};
+
+const UNAME_FILE: &str = "uname-r";
+const SYSPATCH_FILE: &str = "syspatch-l";
+
+struct Host {
+    name: String,
+    release: String,
+    version: u16,
+    installed: Vec<String>,
+}
+
+struct MissingPatch<'a> {
+    entry: &'a AtomEntry,
+    days: i64,
+}
+
+/// Every subdirectory of `dir` is a host holding the output of `uname -r` and `syspatch -l`.
+fn read_hosts(dir: &str) -> Vec<Host> {
+    let dir_entries = match fs::read_dir(dir) {
+        Ok(dir_entries) => dir_entries,
+        Err(e) => {
+            eprintln!("Error reading fleet directory {}: {}", dir, e);
+            exit(1);
+        }
+    };
+    let mut hosts = Vec::<Host>::new();
+    for host_dir in dir_entries.flatten().filter(|e| e.path().is_dir()) {
+        let name = host_dir.file_name().to_string_lossy().to_string();
+        let release = match fs::read_to_string(host_dir.path().join(UNAME_FILE)) {
+            Ok(release) => release.trim().to_string(),
+            Err(e) => {
+                eprintln!("Skipping host {}: cannot read {}: {}", name, UNAME_FILE, e);
+                continue;
+            }
+        };
+        let version = match parse_release(&release) {
+            Some(version) => version,
+            None => {
+                eprintln!("Skipping host {}: invalid release {:?}", name, release);
+                continue;
+            }
+        };
+        let syspatch_path = host_dir.path().join(SYSPATCH_FILE);
+        let installed = match read_installed(&syspatch_path.to_string_lossy(), version) {
+            Ok(installed) => installed,
+            Err(e) => {
+                eprintln!("Skipping host {}: {}", name, e);
+                continue;
+            }
+        };
+        hosts.push(Host {
+            name,
+            release,
+            version,
+            installed,
+        });
+    }
+    hosts.sort_by(|a, b| a.name.cmp(&b.name));
+    hosts
+}
+
+fn render_markdown(report: &[(Host, Vec<MissingPatch>)]) -> String {
+    let mut md = String::from("# Fleet syspatch report\n");
+    for (host, missing) in report {
+        md += &format!(
+            "\n## {} (OpenBSD {}): {} missing\n",
+            host.name,
+            host.release,
+            missing.len()
+        );
+        if missing.is_empty() {
+            continue;
+        }
+        md += "\n| Patch | Kind | Date | Days missing |\n|---|---|---|---|\n";
+        for m in missing {
+            md += &format!(
+                "| [{}]({}) | {} | {} | {} |\n",
+                patch_name(m.entry).unwrap_or_default(),
+                m.entry.link,
+                m.entry.kind,
+                m.entry.updated.format("%Y-%m-%d"),
+                m.days
+            );
+        }
+    }
+    md
+}
+
+fn render_html(report: &[(Host, Vec<MissingPatch>)]) -> String {
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n",
+        "<html>\n<head><meta charset=\"utf-8\"><title>Fleet syspatch report</title></head>\n",
+        "<body>\n<h1>Fleet syspatch report</h1>\n"
+    ));
+    for (host, missing) in report {
+        html += &format!(
+            "<h2>{} (OpenBSD {}): {} missing</h2>\n",
+            html_escape::encode_text(&host.name),
+            html_escape::encode_text(&host.release),
+            missing.len()
+        );
+        if missing.is_empty() {
+            continue;
+        }
+        html += "<table>\n<tr><th>Patch</th><th>Kind</th><th>Date</th><th>Days missing</th></tr>\n";
+        for m in missing {
+            html += &format!(
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                html_escape::encode_double_quoted_attribute(&m.entry.link),
+                html_escape::encode_text(patch_name(m.entry).unwrap_or_default()),
+                html_escape::encode_text(&m.entry.kind),
+                m.entry.updated.format("%Y-%m-%d"),
+                m.days
+            );
+        }
+        html += "</table>\n";
+    }
+    html + "</body>\n</html>\n"
+}
+
+fn render_json(report: &[(Host, Vec<MissingPatch>)]) -> String {
+    let hosts: Vec<_> = report
+        .iter()
+        .map(|(host, missing)| {
+            json!({
+                "host": host.name,
+                "release": host.release,
+                "missing": missing.iter().map(|m| json!({
+                    "patch": patch_name(m.entry),
+                    "kind": m.entry.kind,
+                    "date": m.entry.updated.format("%Y-%m-%d").to_string(),
+                    "days_missing": m.days,
+                    "link": m.entry.link,
+                })).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    json!({ "hosts": hosts }).to_string()
+}
+
+pub async fn run(args: &[String]) {
+    let (dir, format) = match args {
+        [dir] => (dir, "markdown"),
+        [dir, flag, format] if flag == "--format" => (dir, format.as_str()),
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(1);
+        }
+    };
+    let render = match format {
+        "markdown" => render_markdown,
+        "html" => render_html,
+        "json" => render_json,
+        _ => {
+            eprintln!("Unknown report format: {}", format);
+            exit(1);
+        }
+    };
+
+    let hosts = read_hosts(dir);
+    let (min_version, max_version) = match (
+        hosts.iter().map(|h| h.version).min(),
+        hosts.iter().map(|h| h.version).max(),
+    ) {
+        (Some(min), Some(max)) => (min, max),
+        _ => {
+            eprintln!("No hosts found in {}", dir);
+            exit(1);
+        }
+    };
+    let entries = fetch_entries(min_version..=max_version)
+        .await
+        .unwrap_or_else(|e| e.exit());
+
+    let now = Utc::now();
+    let report: Vec<(Host, Vec<MissingPatch>)> = hosts
+        .into_iter()
+        .map(|host| {
+            let release_entries = entries.iter().filter(|e| e.release_version == host.version);
+            let missing = missing_patches(release_entries, &host.installed)
+                .into_iter()
+                .map(|entry| MissingPatch {
+                    entry,
+                    days: (now - entry.updated.with_timezone(&Utc)).num_days(),
+                })
+                .collect();
+            (host, missing)
+        })
+        .collect();
+    print!("{}", render(&report));
+}
@@ -0,0 +1,97 @@
+use crate::{config::GitConfig, publisher::PublishError};
+use std::path::Path;
+use tokio::{fs, process::Command};
+
+/// Runs git in the working copy and returns its standard output untouched.
+async fn git_output(config: &GitConfig, args: &[&str]) -> Result<String, String> {
+    let output = match Command::new("git")
+        //Messages are matched below, so they can't be translated
+        .env("LC_ALL", "C")
+        .arg("-C")
+        .arg(&config.repo_dir)
+        .args(args)
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) => return Err(format!("cannot run git: {}", e)),
+    };
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+        false => Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+async fn git(config: &GitConfig, args: &[&str]) -> Result<String, String> {
+    Ok(git_output(config, args).await?.trim().to_string())
+}
+
+/// Checks out the branch, pulls it when there is a remote and returns the feed file as committed
+/// in `HEAD` with its blob SHA, which is what `calc_git_sha1` computes. Both are empty when the
+/// file isn't in `HEAD` yet.
+pub async fn read_feed(config: &GitConfig) -> Result<(String, String), String> {
+    git(config, &["checkout", "--quiet", &config.branch]).await?;
+    if let Some(ref remote) = config.remote {
+        git(
+            config,
+            &["pull", "--quiet", "--ff-only", remote, &config.branch],
+        )
+        .await?;
+    }
+    //"<mode> blob <sha>\t<path>", or nothing when the path isn't in the tree
+    let entry = git(config, &["ls-tree", "HEAD", "--", &config.file]).await?;
+    let sha = match entry.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => return Ok((String::new(), String::new())),
+        [_, "blob", sha, ..] => sha.to_string(),
+        _ => return Err(format!("{} is not a file in HEAD", config.file)),
+    };
+    let feed = git_output(config, &["cat-file", "blob", &sha]).await?;
+    Ok((feed, sha))
+}
+
+/// Commits the feed and pushes it when asked to. A push the remote rejects because it moved
+/// is a conflict, and any failed push undoes the commit so the next pull can fast-forward.
+pub async fn commit(config: &GitConfig, feed: &str, message: &str) -> Result<(), PublishError> {
+    let parent = git(config, &["rev-parse", "HEAD"]).await?;
+    let path = Path::new(&config.repo_dir).join(&config.file);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, feed)
+        .await
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    git(config, &["add", "--", &config.file]).await?;
+    git(
+        config,
+        &[
+            "-c",
+            &format!("user.name={}", config.author_name),
+            "-c",
+            &format!("user.email={}", config.author_email),
+            "commit",
+            "--quiet",
+            "-m",
+            message,
+            "--",
+            &config.file,
+        ],
+    )
+    .await?;
+    if let (Some(ref remote), true) = (&config.remote, config.push) {
+        if let Err(e) = git(config, &["push", "--quiet", remote, &config.branch]).await {
+            git(config, &["reset", "--quiet", "--keep", &parent]).await?;
+            return Err(match e.contains("[rejected]") {
+                true => PublishError::Conflict(e),
+                false => PublishError::Failed(e),
+            });
+        }
+    }
+    Ok(())
+}
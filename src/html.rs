@@ -1,6 +1,6 @@
 use crate::{
-    FEED_AUTHOR_NAME, GITHUB_API_VERSION, GITHUB_COMMIT_EMAIL, GITHUB_COMMIT_MESSAGE,
-    GITHUB_REPO_OWNER,
+    publisher::{decode_content, PublishError},
+    FEED_AUTHOR_NAME, GITHUB_API_VERSION, GITHUB_COMMIT_EMAIL, GITHUB_REPO_OWNER,
 };
 use bytes::BufMut;
 use html5ever::{
@@ -14,12 +14,12 @@ use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
     StatusCode,
 };
-use serde_json::json;
+use serde_json::{json, Value};
 use sha::{
     sha1::Sha1,
     utils::{Digest, DigestExt},
 };
-use std::{io::BufWriter, process::exit};
+use std::{fmt, io::BufWriter, process::exit};
 
 pub fn parse_html(html: &mut String) -> RcDom {
     match parse_document(RcDom::default(), ParseOpts::default())
@@ -34,6 +34,7 @@ pub fn parse_html(html: &mut String) -> RcDom {
     }
 }
 
+#[allow(clippy::single_match)]
 pub fn cerealize(node: Handle) -> String {
     let mut content_buf = BufWriter::new(Vec::new());
 
@@ -63,6 +64,7 @@ pub fn cerealize(node: Handle) -> String {
     }
 }
 
+#[allow(clippy::needless_borrow)]
 pub(crate) fn calc_git_sha1(bytes: &[u8]) -> String {
     let mut blob = Vec::<u8>::new();
     blob.put_slice(format!("blob {}", bytes.len()).as_bytes());
@@ -71,15 +73,30 @@ pub(crate) fn calc_git_sha1(bytes: &[u8]) -> String {
     Sha1::default().digest(blob.as_bytes()).to_hex()
 }
 
-pub async fn http_get(url: &str, git_sha: bool) -> Result<(String, Option<String>), u16> {
-    println!("Fetching url: {}", url);
-    let res = match reqwest::get(url).await {
-        Ok(res) => res,
-        Err(e) => {
-            eprintln!("Error Fetching URL: {}", e);
-            exit(1);
+/// Why `http_get` got no page. Only `NotFound` is expected, like for a release not out yet.
+#[derive(Debug)]
+pub enum HttpError {
+    NotFound,
+    Status(StatusCode, String),
+    Failed(String),
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpError::NotFound => f.write_str("Not Found"),
+            HttpError::Status(status, url) => write!(f, "Server Error: {} URL: {}", status, url),
+            HttpError::Failed(e) => f.write_str(e),
         }
-    };
+    }
+}
+
+#[allow(clippy::clone_on_copy)]
+pub async fn http_get(url: &str, git_sha: bool) -> Result<(String, Option<String>), HttpError> {
+    println!("Fetching url: {}", url);
+    let res = reqwest::get(url)
+        .await
+        .map_err(|e| HttpError::Failed(format!("Error Fetching URL: {}", e)))?;
     let status = res.status().clone();
     let ret = match res.bytes().await {
         Ok(bytes) => {
@@ -88,35 +105,26 @@ pub async fn http_get(url: &str, git_sha: bool) -> Result<(String, Option<String
                     (text, Some(calc_git_sha1(&bytes)))
                 } //File exists, replace
                 Ok(text) => (text, None), //File doesn't exist or error
-                Err(e) => {
-                    eprintln!("Error parsing response: {}", e);
-                    exit(1);
-                }
+                Err(e) => return Err(HttpError::Failed(format!("Error parsing response: {}", e))),
             }
         }
-        Err(e) => {
-            eprintln!("Response Error: {}", e);
-            exit(1);
-        }
+        Err(e) => return Err(HttpError::Failed(format!("Response Error: {}", e))),
     };
     match status {
         StatusCode::OK => Ok(ret),
-        StatusCode::NOT_FOUND => Err(404),
-        _ => {
-            eprintln!("Server Error: {} URL: {}", status, &url);
-            exit(2);
-        }
+        StatusCode::NOT_FOUND => Err(HttpError::NotFound),
+        _ => Err(HttpError::Status(status, url.to_string())),
     }
 }
 
-pub async fn github_commit(url: &str, token: &str, content: &str, sha: &str) -> Result<(), u16> {
+pub(crate) fn github_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.insert(
         header::ACCEPT,
         match HeaderValue::from_str("application/vnd.github+json") {
             Ok(value) => value,
             Err(_) => {
-                eprintln!("Error with Accept header on github_commit()");
+                eprintln!("Error with Accept header on GitHub request");
                 exit(1);
             }
         },
@@ -126,7 +134,7 @@ pub async fn github_commit(url: &str, token: &str, content: &str, sha: &str) ->
         match HeaderValue::from_str(GITHUB_API_VERSION) {
             Ok(value) => value,
             Err(_) => {
-                eprintln!("Error with X-GitHub-Api-Version header on github_commit()");
+                eprintln!("Error with X-GitHub-Api-Version header on GitHub request");
                 exit(1);
             }
         },
@@ -136,50 +144,114 @@ pub async fn github_commit(url: &str, token: &str, content: &str, sha: &str) ->
         match HeaderValue::from_str(GITHUB_REPO_OWNER) {
             Ok(value) => value,
             Err(_) => {
-                eprintln!("Error with User-Agent header on github_commit()");
+                eprintln!("Error with User-Agent header on GitHub request");
                 exit(1);
             }
         },
     );
-    let req_body = json!({
-        "message": GITHUB_COMMIT_MESSAGE,
+    headers
+}
+
+/// Reads a file through the contents API. `Ok(None)` when it doesn't exist yet.
+pub async fn github_get(url: &str, token: &str) -> Result<Option<(String, String)>, String> {
+    println!("Fetching url: {}", url);
+    let res = reqwest::Client::new()
+        .get(url)
+        .headers(github_headers())
+        .bearer_auth(token)
+        .send()
+        .await
+        .map_err(|e| format!("Error Fetching URL: {}", e))?;
+    let status = res.status();
+    let file = res
+        .json::<Value>()
+        .await
+        .map_err(|e| format!("Error parsing response: {}", e))?;
+    match status {
+        StatusCode::OK => (),
+        StatusCode::NOT_FOUND => return Ok(None),
+        _ => {
+            return Err(format!(
+                "GitHub Server Error: {}, Response Body: {:?}",
+                status, file
+            ))
+        }
+    };
+
+    let sha = file["sha"].as_str().unwrap_or_default().to_string();
+    //Files over 1 MB come without content, ask for the raw media type instead
+    if file["encoding"] != "base64" {
+        let mut headers = github_headers();
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/vnd.github.raw+json"),
+        );
+        let res = reqwest::Client::new()
+            .get(url)
+            .headers(headers)
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|e| format!("Error Fetching URL: {}", e))?;
+        return match res.status() {
+            StatusCode::OK => match res.text().await {
+                Ok(content) => Ok(Some((content, sha))),
+                Err(e) => Err(format!("Response Error: {}", e)),
+            },
+            status => Err(format!("GitHub Server Error: {}", status)),
+        };
+    }
+    match decode_content(&file["content"]) {
+        Ok(content) => Ok(Some((content, sha))),
+        Err(e) => Err(format!("Error decoding GitHub file content: {}", e)),
+    }
+}
+
+pub async fn github_commit(
+    url: &str,
+    token: &str,
+    content: &str,
+    sha: &str,
+    message: &str,
+) -> Result<(), PublishError> {
+    let mut req_body = json!({
+        "message": message,
         "committer": {
             "name": FEED_AUTHOR_NAME,
             "email": GITHUB_COMMIT_EMAIL
         },
-        "content": content,
-        "sha": sha
-    })
-    .to_string();
+        "content": content
+    });
+    //Without a sha the file is created
+    if !sha.is_empty() {
+        req_body["sha"] = Value::from(sha);
+    }
+    let req_body = req_body.to_string();
 
-    let res = match reqwest::Client::new()
+    let res = reqwest::Client::new()
         .put(url)
-        .headers(headers)
+        .headers(github_headers())
         .bearer_auth(token)
-        .body(req_body.clone())
+        .body(req_body)
         .send()
         .await
-    {
-        Ok(res) => res,
-        Err(e) => {
-            eprintln!("Error Fetching URLS: {}", e);
-            exit(1);
-        }
-    };
+        .map_err(|e| format!("Error Fetching URLS: {}", e))?;
     let status = res.status().as_u16();
-    let txt = match res.text().await {
-        Ok(txt) => txt,
-        Err(e) => {
-            eprintln!("Error receiving response: {}", e);
-            exit(1);
-        }
-    };
+    let txt = res
+        .text()
+        .await
+        .map_err(|e| format!("Error receiving response: {}", e))?;
 
     match status {
         200 | 201 => Ok(()),
-        code => {
-            eprintln!("GitHub Server Error: {}, Response Body: {:?}", code, txt);
-            exit(2);
-        }
+        //The file changed since its sha was fetched
+        409 | 422 => Err(PublishError::Conflict(format!(
+            "GitHub Conflict: {}, Response Body: {:?}",
+            status, txt
+        ))),
+        code => Err(PublishError::Server(format!(
+            "GitHub Server Error: {}, Response Body: {:?}",
+            code, txt
+        ))),
     }
 }
@@ -1,30 +1,61 @@
 extern crate core;
 
 use crate::atom_entry::AtomEntry;
+use crate::config::{ChangesFormat, Config, Credential, FeedConfig, PublisherKind};
+use crate::cve::CveRegexes;
 use crate::date_index::DateIndex;
-use crate::html::{calc_git_sha1, cerealize, github_commit, http_get, parse_html};
+use crate::html::{calc_git_sha1, cerealize, http_get, parse_html, HttpError};
+use crate::nvd::Severity;
+use crate::patch::PatchCache;
+use crate::previous_feed::PreviousEntry;
+use crate::publisher::{GitHub, GitLab, Gitea, LocalGit, PublishError, Publisher};
+use crate::s3::S3;
+use crate::signify::SignatureStatus;
 use crate::traverse_dom::{TraverseAttrs, TraverseDom};
 
-use base64::Engine;
 use chrono::{DateTime, Duration, FixedOffset, Utc};
 use html5ever::tendril::StrTendril;
 use markup5ever_rcdom::{Handle, RcDom};
 use regex::Regex;
-use std::{fs, os::unix::fs::PermissionsExt, process::exit, str::FromStr, u16};
+use std::{env, fmt, fs, ops::RangeInclusive, process::exit, str::FromStr};
+use tera::Tera;
 
 mod atom_entry;
+mod audit;
+mod changes;
+mod config;
+mod credentials;
+mod crypto;
+mod cve;
+mod daemon;
 mod date_index;
+mod email;
+mod fleet;
+mod git;
 mod html;
+mod mastodon;
+mod nvd;
+mod patch;
+mod previous_feed;
+mod publisher;
+mod s3;
+mod secret_file;
+mod serve;
+mod signify;
+mod site;
+mod templates;
 mod traverse_dom;
+mod validate;
+mod webhook;
+mod websub;
 
 const ERRATA_URL: &str = "https://www.openbsd.org/errata";
 const PATCHES_URL: &str = "https://ftp.openbsd.org/pub/OpenBSD/patches/";
 const HOME_PAGE_URL: &str = "https://www.openbsd.org/index.html";
 
 const ISO_UTC_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+const ATOM_CONTENT_TYPE: &str = "application/atom+xml; charset=utf-8";
 
-const GITHUB_RAW_URL: &str =
-    "https://github.com/AlbertGoma/syspatch-feed.albert.goma.cat/raw/main/pub/atom.xml";
 const GITHUB_COMMIT_URL: &str =
     "https://api.github.com/repos/AlbertGoma/syspatch-feed.albert.goma.cat/contents/pub/atom.xml";
 const GITHUB_REPO_OWNER: &str = "AlbertGoma";
@@ -32,6 +63,25 @@ const GITHUB_COMMIT_MESSAGE: &str = "Automated atom feed update";
 const GITHUB_COMMIT_EMAIL: &str = "58812649+AlbertGoma@users.noreply.github.com";
 const GITHUB_API_VERSION: &str = "2022-11-28";
 const GITHUB_TOKEN_PATH: &str = "/etc/syspatch-feed-token";
+const CONFIG_PATH: &str = "/etc/syspatch-feed.json";
+
+const EXIT_SERVER_ERROR: i32 = 2;
+const EXIT_INVALID_FEED: i32 = 3;
+const EXIT_ENTRY_LOSS: i32 = 4;
+
+const USAGE: &str = concat!(
+    "Usage: syspatch_feed [command]\n",
+    "    (no command)               Publish the feed\n",
+    "    daemon                     Publish the feed periodically until SIGTERM,\n",
+    "                               reloading the configuration on SIGHUP\n",
+    "    serve                      Serve the feed over HTTP, regenerating it like daemon\n",
+    "    audit <release> <path>     List the patches missing from a host, given its\n",
+    "                               /var/syspatch directory or `syspatch -l` output\n",
+    "    fleet-report <dir> [--format markdown|html|json]\n",
+    "                               Report the patches missing from every host in <dir>\n",
+    "    cve <CVE-YYYY-NNNN> [release]\n",
+    "                               Find the errata that fix a CVE",
+);
 
 const FEED_TITLE: &str = "OpenBSD Patches";
 const FEED_LINK: &str = "https://syspatch.albert.goma.cat/atom.xml";
@@ -39,13 +89,75 @@ const FEED_LINK_REL: &str = "https://www.openbsd.org";
 const FEED_AUTHOR_NAME: &str = "Albert Gomà i León";
 const FEED_AUTHOR_URI: &str = "https://albert.goma.cat";
 const FEED_UUID: &str = "tag:albert.goma.cat,2023:feed/openbsd/sypatch";
+const FEED_ID_PREFIX: &str = "tag:albert.goma.cat,2023:syspatch_feed";
+
+/// Why a run failed. `code` is the exit status of the process when it ends it.
+#[derive(Debug)]
+pub struct RunError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl RunError {
+    fn new(code: i32, message: String) -> RunError {
+        RunError { code, message }
+    }
+
+    fn context(self, what: &str) -> RunError {
+        RunError::new(self.code, format!("{}: {}", what, self.message))
+    }
 
+    /// Prints the error and ends the process, for one-shot commands.
+    pub fn exit(self) -> ! {
+        eprintln!("{}", self.message);
+        exit(self.code);
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for RunError {
+    fn from(message: String) -> RunError {
+        RunError::new(1, message)
+    }
+}
+
+impl From<HttpError> for RunError {
+    fn from(e: HttpError) -> RunError {
+        match e {
+            HttpError::Status(..) => RunError::new(EXIT_SERVER_ERROR, e.to_string()),
+            e => RunError::new(1, e.to_string()),
+        }
+    }
+}
+
+impl From<PublishError> for RunError {
+    fn from(e: PublishError) -> RunError {
+        match e {
+            //A conflict left after the retries is the server's doing too
+            PublishError::Server(e) | PublishError::Conflict(e) => {
+                RunError::new(EXIT_SERVER_ERROR, e)
+            }
+            e => RunError::new(1, e.to_string()),
+        }
+    }
+}
+
+fn missing_tag(tag: &str) -> RunError {
+    RunError::from(format!("Document Error: Missing <{}> tag", tag))
+}
+
+#[allow(clippy::ptr_arg)]
 fn get_title(
     patch: &Handle,
     entries: &mut Vec<AtomEntry>,
     version: u16,
     date_regex: &Regex,
-) -> Option<String> {
+) -> Result<Option<String>, RunError> {
     match patch.first_child_by_name("strong") {
         Some(strong) => {
             let mut text = strong.get_first_text();
@@ -58,7 +170,11 @@ fn get_title(
             });
             //Strip the date from the title
             text.truncate(date_offset);
-            Some(format!("OpenBSD {:.1}, {}", version as f32 / 10., text))
+            Ok(Some(format!(
+                "OpenBSD {:.1}, {}",
+                version as f32 / 10.,
+                text
+            )))
         }
         None => {
             match entries
@@ -67,12 +183,13 @@ fn get_title(
             {
                 Some(last_entry) => last_entry,
                 None => {
-                    eprintln!("Document Error: <li> without <strong> is first element");
-                    exit(1);
+                    return Err(RunError::from(String::from(
+                        "Document Error: <li> without <strong> is first element",
+                    )))
                 }
             }
             .content += &cerealize(patch.clone());
-            None
+            Ok(None)
         }
     }
 }
@@ -84,32 +201,24 @@ fn get_id(patch: &Handle, version: u16, iteration: usize) -> String {
     }
 }
 
-fn get_patches(dom: &RcDom) -> Vec<Handle> {
-    match match match &dom.document.first_child_by_name("html") {
-        Some(html) => html,
-        None => {
-            eprintln!("Document Error: Missing <html> tag");
-            exit(1);
-        }
-    }
-    .first_child_by_name("body")
-    {
-        Some(body) => body,
-        None => {
-            eprintln!("Document Error: Missing <body> tag");
-            exit(1);
-        }
-    }
-    .first_child_by_name("ul")
+fn get_patches(dom: &RcDom) -> Result<Vec<Handle>, RunError> {
+    match dom
+        .document
+        .first_child_by_name("html")
+        .ok_or_else(|| missing_tag("html"))?
+        .first_child_by_name("body")
+        .ok_or_else(|| missing_tag("body"))?
+        .first_child_by_name("ul")
     {
-        Some(ul) => ul.children_by_name("li"),
+        Some(ul) => Ok(ul.children_by_name("li")),
         None => {
             eprintln!("Document Error: Missing <ul> tag. New release?");
-            Vec::<Handle>::new()
+            Ok(Vec::<Handle>::new())
         }
     }
 }
 
+#[allow(clippy::unwrap_or_default)]
 fn make_up_date(last_good_date: &DateTime<FixedOffset>) -> DateTime<FixedOffset> {
     last_good_date
         .checked_add_signed(Duration::days(1))
@@ -124,229 +233,219 @@ async fn get_updated_date(
     last_good_date: &DateTime<FixedOffset>,
     version: u16,
     iteration: usize,
-) -> DateTime<FixedOffset> {
-    match match date_regex.find(content) {
-        Some(result) => DateTime::parse_from_str(
-            &(String::from(result.as_str()) + " 00:00:00+0000"),
-            "%B %d, %Y %T%z",
-        ),
-        None => {
-            if let Some(ref idx) = date_idx.lazy_load(version /*, request_ctr*/).await {
-                eprintln!("Date unavailable. Parsing from ftp...");
-                let id = &id[id.find("-").map_or(0, |i| i + 1)..];
-
-                let empty = StrTendril::new();
-                let item = idx.get(id);
-                println!("date_idx.get({}) = {:?}", id, item);
-                let mut date_str = item.unwrap_or(&empty).trim_start();
-                date_str = &date_str[..date_str.find(" ").unwrap_or(date_str.len())];
-                let date_str = date_str.to_string() + " 00:00:00+0000";
-
-                DateTime::parse_from_str(&date_str, "%d-%b-%Y %T%z")
-            } else {
-                Ok(make_up_date(last_good_date))
+) -> Result<DateTime<FixedOffset>, RunError> {
+    Ok(
+        match match date_regex.find(content) {
+            Some(result) => DateTime::parse_from_str(
+                &(String::from(result.as_str()) + " 00:00:00+0000"),
+                "%B %d, %Y %T%z",
+            ),
+            None => {
+                if let Some(ref idx) = date_idx.lazy_load(version /*, request_ctr*/).await? {
+                    eprintln!("Date unavailable. Parsing from ftp...");
+                    let id = &id[id.find("-").map_or(0, |i| i + 1)..];
+
+                    let empty = StrTendril::new();
+                    let item = idx.get(id);
+                    println!("date_idx.get({}) = {:?}", id, item);
+                    let mut date_str = item.unwrap_or(&empty).trim_start();
+                    date_str = &date_str[..date_str.find(" ").unwrap_or(date_str.len())];
+                    let date_str = date_str.to_string() + " 00:00:00+0000";
+
+                    DateTime::parse_from_str(&date_str, "%d-%b-%Y %T%z")
+                } else {
+                    Ok(make_up_date(last_good_date))
+                }
             }
-        }
-    } {
-        Ok(date) => date
-            .checked_add_signed(Duration::seconds(iteration as i64))
-            .unwrap_or(date),
-        Err(e) => {
-            eprintln!("Date Parse Error: {} ---> Making up a new one", e);
-            make_up_date(last_good_date)
-        }
-    }
+        } {
+            Ok(date) => date
+                .checked_add_signed(Duration::seconds(iteration as i64))
+                .unwrap_or(date),
+            Err(e) => {
+                eprintln!("Date Parse Error: {} ---> Making up a new one", e);
+                make_up_date(last_good_date)
+            }
+        },
+    )
 }
 
-fn render_feed(old_feed: &str, entries: &mut Vec<AtomEntry>, sha: &str) -> Option<String> {
-    let header_ending = old_feed
-        .match_indices("\n")
-        .nth(10)
-        .map_or(old_feed.len(), |(h, _)| h + 1);
-    let old_header = &old_feed[..header_ending];
-    let new_header = format!(
-        concat!(
-            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
-            "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
-            "    <title>{title}</title>\n",
-            "    <link rel=\"self\" href=\"{link}\"/>\n",
-            "    <link rel=\"related\" href=\"{link_rel}\"/>\n",
-            "    <updated>{updated}</updated>\n",
-            "    <author>\n",
-            "        <name>{author_name}</name>\n",
-            "        <uri>{author_uri}</uri>\n",
-            "    </author>\n",
-            "    <id>{id}</id>\n",
-        ),
-        title = FEED_TITLE,
-        link = FEED_LINK,
-        link_rel = FEED_LINK_REL,
-        updated = Utc::now().format(ISO_UTC_FORMAT),
-        author_name = FEED_AUTHOR_NAME,
-        author_uri = FEED_AUTHOR_URI,
-        id = FEED_UUID
-    );
-    let mut new_feed = String::new();
-    for entry in entries {
-        new_feed += &format!(
-            concat!(
-                "   <entry>\n",
-                "       <id>{id_prefix}/{id}</id>\n",
-                "       <title type=\"html\">{title}</title>\n",
-                "       <updated>{updated}</updated>\n",
-                "       <content type=\"html\">{content}</content>\n",
-                "       <link rel=\"alternate\" type=\"text/html\" href=\"{link}\"/>\n",
-                "   </entry>\n"
-            ),
-            id_prefix = "tag:albert.goma.cat,2023:syspatch_feed",
-            id = entry.id,
-            title = html_escape::encode_safe(&entry.title),
-            updated = entry.updated.format(ISO_UTC_FORMAT),
-            content = html_escape::encode_safe(&entry.content),
-            link = entry.link
-        );
-    }
-    new_feed += "</feed>";
-
-    if calc_git_sha1((old_header.to_owned() + &new_feed).as_bytes()) != sha {
-        Some(new_header + &new_feed)
-    } else {
-        None
-    }
+fn get_patch_url(content: &str, patch_regex: &Regex) -> Option<String> {
+    patch_regex
+        .captures(content)
+        .map(|c| html_escape::decode_html_entities(&c[1]).to_string())
 }
 
-async fn get_latest_version() -> u16 {
-    let mut front_page_html = match http_get(HOME_PAGE_URL, false).await {
-        Ok((html, _)) => html,
-        Err(e) => {
-            eprintln!("Error fetching OpenBSD's website front page: {}", e);
-            exit(1);
-        }
+async fn process_patches(
+    entries: &mut [AtomEntry],
+    config: &Config,
+    patch_regex: &Regex,
+) -> Result<(), RunError> {
+    let cache = PatchCache::new(&config.patches.cache_dir);
+    let keys = match config.signify.enabled {
+        true => signify::load_keys(&config.signify.keys_dir)?,
+        false => Vec::new(),
     };
-    let front_page_dom = parse_html(&mut front_page_html);
+    let mut bad_signatures = 0;
+    for entry in entries.iter_mut() {
+        entry.patch_url = get_patch_url(&entry.content, patch_regex);
+        //A patch that can't be downloaded only fails the run in strict mode, below
+        let (patch, error) = match entry.patch_url {
+            Some(ref url) => match cache.get(url).await {
+                Ok(patch) => (patch, None),
+                Err(e) => {
+                    eprintln!("Error fetching patch of {}: {}", entry.id, e);
+                    (None, Some(e.to_string()))
+                }
+            },
+            None => (None, None),
+        };
 
-    match match match match match match match match &front_page_dom
-        .document
-        .first_child_by_name("html")
-    {
-        Some(html) => html,
-        None => {
-            eprintln!("Document Error: Missing <html> tag");
-            exit(1);
-        }
-    }
-    .first_child_by_name("body")
-    {
-        Some(main) => main,
-        None => {
-            eprintln!("Document Error: Missing <body> tag");
-            exit(1);
+        if config.signify.enabled {
+            let status = match (&patch, error) {
+                (Some(sig_file), _) => signify::verify_embedded(sig_file.as_bytes(), &keys),
+                (None, Some(e)) => SignatureStatus::Unavailable(e),
+                (None, None) => SignatureStatus::Unsigned,
+            };
+            match status {
+                SignatureStatus::BadSignature(ref reason) => {
+                    eprintln!("Signify Error: {} ({})", entry.id, reason);
+                    bad_signatures += 1;
+                }
+                SignatureStatus::Unavailable(_) => bad_signatures += 1,
+                SignatureStatus::UnknownKey(ref keynum) => eprintln!(
+                    "Signify Warning: {} is signed with key {}, not found in {}",
+                    entry.id, keynum, config.signify.keys_dir
+                ),
+                _ => (),
+            }
+            entry.content += &format!("<p><b>Signature:</b> {}</p>", status);
+            entry.signature = Some(status);
         }
-    }
-    .first_child_by_name("main")
-    {
-        Some(main) => main,
-        None => {
-            eprintln!("Document Error: Missing <main> tag");
-            exit(1);
+
+        if let Some(ref patch) = patch {
+            entry.files = patch::diffstat(patch);
         }
-    }
-    .first_child_by_name("article")
-    {
-        Some(article) => article,
-        None => {
-            eprintln!("Document Error: Missing <article> tag");
-            exit(1);
+        if config.patches.classify {
+            entry.impact = patch::classify(&entry.files);
+            if let Some(ref impact) = entry.impact {
+                entry.content += &format!("<p><b>Impact:</b> {}</p>", impact.label());
+            }
         }
-    }
-    .first_child_by_name("h2")
-    {
-        Some(h2) => h2,
-        None => {
-            eprintln!("Document Error: Missing <h2> tag");
-            exit(1);
+        if config.patches.diffstat && !entry.files.is_empty() {
+            entry.content += &patch::render_diffstat(&entry.files);
         }
     }
-    .first_child_by_name("a")
-    {
-        Some(a) => a.data.first_attr_by_name("href"),
-        None => {
-            eprintln!("Document Error: Missing <a> tag");
-            exit(1);
-        }
-    } {
-        Some(href) => u16::from_str(&href[..href.len() - 5]),
-        None => {
-            eprintln!("Document Error: Missing href attribute in <a> tag");
-            exit(1);
-        }
-    } {
-        Ok(version) => version,
-        Err(e) => {
-            eprintln!("Error parsing version number: {}", e);
-            exit(1);
-        }
+    if config.signify.strict && bad_signatures > 0 {
+        return Err(RunError::from(format!(
+            "Strict mode: {} patch(es) failed or missed signature verification",
+            bad_signatures
+        )));
     }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() {
-    //Read GitHub secret from file
-    let (config_file_attrs, file_type) = match fs::metadata(GITHUB_TOKEN_PATH) {
-        Ok(meta) => (meta.permissions().mode(), meta.file_type()),
-        Err(e) => {
-            eprintln!(
-                "Error opening GitHub authentication token file in \"{}\": {:}",
-                GITHUB_TOKEN_PATH, e
-            );
-            exit(1);
-        }
-    };
+/// `<updated>` of the previous feed, so an unchanged feed renders to the very same bytes.
+fn get_feed_updated(feed: &str) -> Option<&str> {
+    let start = feed.find("<updated>")? + "<updated>".len();
+    let end = feed[start..].find("</updated>")? + start;
+    Some(&feed[start..end])
+}
 
-    if !file_type.is_file() {
-        eprintln!("Error: {} should be a file", GITHUB_TOKEN_PATH);
-        exit(1);
-    }
-    //Regular file permissions bitmask:
-    //0b_0100_ugs_rwx_rwx_rwx   //S_IFREG can have other values in non-strictly POSIX systems
-    //0b_xxxx_xxx_1xx_xxx_000   (x = don't care)
-    match config_file_attrs as u16 ^ 0b_0100_000_100_000_000u16 {
-        res if res << 13 != 0 || (res << 7) >> 15 != 0 => {
-            eprintln!(
-                "Permissions Error: Only the owner and group should be able to access {}",
-                GITHUB_TOKEN_PATH
-            );
-            exit(1);
-        }
-        _ => (),
+fn render_feed(
+    tera: &Tera,
+    feed: &FeedConfig,
+    old_feed: &str,
+    entries: &[AtomEntry],
+    sha: &str,
+) -> Result<Option<String>, RunError> {
+    let render = |updated: &str| {
+        let context = templates::feed_context(feed, updated, entries);
+        templates::render(tera, templates::FEED_TEMPLATE, &context)
+            .map_err(|e| RunError::from(format!("Error rendering feed: {}", e)))
     };
 
-    let token = match fs::read_to_string(GITHUB_TOKEN_PATH) {
-        Ok(secret) => secret,
-        Err(e) => {
-            eprintln!(
-                "Error reading GitHub authentication token in {}: {:}",
-                GITHUB_TOKEN_PATH, e
-            );
-            exit(1);
-        }
-    };
+    match get_feed_updated(old_feed) {
+        Some(old_updated) if calc_git_sha1(render(old_updated)?.as_bytes()) == sha => Ok(None),
+        _ => Ok(Some(render(
+            &Utc::now().format(ISO_UTC_FORMAT).to_string(),
+        )?)),
+    }
+}
 
-    //Download latest feed from GitHub
-    let (old_feed, sha) = match http_get(GITHUB_RAW_URL, true).await {
-        Ok((feed, Some(sha))) => (feed, sha),
-        Ok((_, None)) => {
-            eprintln!("Create an empty /pub/atom.xml file manually on the repository");
-            exit(1);
+/// Refuses to publish when too many entries of releases that are still fetched would vanish
+/// from the feed, which is what happens when the errata pages change their markup.
+fn check_entry_loss(
+    previous: &[PreviousEntry],
+    entries: &[AtomEntry],
+    config: &Config,
+    min_version: u16,
+) -> Result<(), RunError> {
+    let new_ids: Vec<String> = entries
+        .iter()
+        .map(|e| format!("{}/{}", config.feed.id_prefix, e.id))
+        .collect();
+    let kept: Vec<&PreviousEntry> = previous
+        .iter()
+        .filter(|p| previous_feed::release_version(&p.id).is_some_and(|v| v >= min_version))
+        .collect();
+    let removed: Vec<&&PreviousEntry> = kept.iter().filter(|p| !new_ids.contains(&p.id)).collect();
+    if removed.is_empty() {
+        return Ok(());
+    }
+
+    let removed_percent = removed.len() as f32 * 100. / kept.len() as f32;
+    if removed_percent > config.guard.max_removed_percent {
+        let mut message = format!(
+            "Guard Error: {} of {} entries ({:.0}%) would disappear from the feed, over the {}% threshold",
+            removed.len(),
+            kept.len(),
+            removed_percent,
+            config.guard.max_removed_percent
+        );
+        for entry in removed {
+            message += &format!("\n    {}", entry.id);
         }
+        return Err(RunError::new(EXIT_ENTRY_LOSS, message));
+    }
+    Ok(())
+}
+
+async fn get_latest_version() -> Result<u16, RunError> {
+    let mut front_page_html = match http_get(HOME_PAGE_URL, false).await {
+        Ok((html, _)) => html,
         Err(e) => {
-            eprintln!("Cannot retrieve previous feed file: {}", e);
-            exit(1);
+            return Err(RunError::from(e).context("Error fetching OpenBSD's website front page"))
         }
     };
+    let front_page_dom = parse_html(&mut front_page_html);
 
-    let mut entries = Vec::<AtomEntry>::new();
-    let date_regex = match Regex::new(concat!(
+    let href = front_page_dom
+        .document
+        .first_child_by_name("html")
+        .ok_or_else(|| missing_tag("html"))?
+        .first_child_by_name("body")
+        .ok_or_else(|| missing_tag("body"))?
+        .first_child_by_name("main")
+        .ok_or_else(|| missing_tag("main"))?
+        .first_child_by_name("article")
+        .ok_or_else(|| missing_tag("article"))?
+        .first_child_by_name("h2")
+        .ok_or_else(|| missing_tag("h2"))?
+        .first_child_by_name("a")
+        .ok_or_else(|| missing_tag("a"))?
+        .data
+        .first_attr_by_name("href")
+        .ok_or_else(|| {
+            RunError::from(String::from(
+                "Document Error: Missing href attribute in <a> tag",
+            ))
+        })?;
+    u16::from_str(&href[..href.len() - 5])
+        .map_err(|e| RunError::from(format!("Error parsing version number: {}", e)))
+}
+
+fn get_date_regex() -> Regex {
+    match Regex::new(concat!(
         //We can't generate it at compile time :(
         "(Jan|January|Feb|February|Mar",
         "|March|Apr|April|May|Jun|June",
@@ -359,30 +458,50 @@ async fn main() {
             eprintln!("Wrong regex: {}", e);
             exit(1);
         }
-    };
+    }
+}
 
-    //Parse latest version
-    let latest_version = get_latest_version().await;
-    let min_version = latest_version - 2;
-    let mut date_idx = DateIndex::new(min_version);
+fn release_name(version: u16) -> String {
+    format!("{:.1}", version as f32 / 10.)
+}
+
+/// Parses a release as printed by `uname -r` ("7.2") into the errata page number (72).
+fn parse_release(release: &str) -> Option<u16> {
+    let (major, minor) = release.trim().split_once('.')?;
+    match (u16::from_str(major), u16::from_str(minor)) {
+        (Ok(major), Ok(minor)) if minor < 10 => Some(major * 10 + minor),
+        _ => None,
+    }
+}
+
+fn get_kind(title: &str) -> String {
+    title[title.rfind(": ").map_or(title.len(), |i| i + 2)..].to_string()
+}
+
+#[allow(clippy::clone_on_copy)]
+async fn fetch_entries(versions: RangeInclusive<u16>) -> Result<Vec<AtomEntry>, RunError> {
+    let mut entries = Vec::<AtomEntry>::new();
+    let date_regex = get_date_regex();
+    let mut date_idx = DateIndex::new(*versions.start());
 
     //Fetch until current release or exit if 404
-    for version in min_version..=latest_version {
+    for version in versions {
         let errata_url = ERRATA_URL.to_owned() + &version.to_string() + ".html";
         let mut errata_html = match http_get(&errata_url, false).await {
             Ok((html, _)) => html,
-            Err(_) => break,
+            Err(HttpError::NotFound) => break,
+            Err(e) => return Err(e.into()),
         };
 
         //Parse the contents into a data structure
         let errata_dom = parse_html(&mut errata_html);
-        let patches = get_patches(&errata_dom);
+        let patches = get_patches(&errata_dom)?;
         let mut last_good_date = DateTime::<FixedOffset>::default();
 
         for (i, patch) in patches.iter().enumerate() {
             // The contents of entries without a title belong to the previous one. Handled
             // inside get_title()
-            let title = match get_title(patch, &mut entries, version, &date_regex) {
+            let title = match get_title(patch, &mut entries, version, &date_regex)? {
                 Some(title) => title,
                 None => continue,
             };
@@ -398,36 +517,370 @@ async fn main() {
                 version,
                 i,
             )
-            .await;
+            .await?;
             last_good_date = updated.clone();
             let link = errata_url.clone() + "#" + &id[id.find("-").map_or(0, |i| i + 1)..];
             let atom_entry = AtomEntry {
                 id,
+                kind: get_kind(&title),
                 title,
                 updated,
                 link,
                 content,
                 release_version: version,
                 iteration_count: i,
+                patch_url: None,
+                signature: None,
+                files: Vec::new(),
+                impact: None,
+                cves: Vec::new(),
+                advisories: Vec::new(),
+                cvss: None,
             };
             entries.push(atom_entry);
         }
     }
-    entries.sort_by(AtomEntry::cmp_entries);
 
-    //Render the feed and checksum for changes
-    if let Some(feed) = render_feed(&old_feed, &mut entries, &sha) {
-        //Upload it back to GitHub
-        match github_commit(
-            GITHUB_COMMIT_URL,
-            token.trim(),
-            &base64::engine::general_purpose::STANDARD.encode(feed),
-            &sha,
-        )
+    let cve_regexes = CveRegexes::new();
+    for entry in entries.iter_mut() {
+        cve::extract_references(entry, &cve_regexes);
+    }
+    Ok(entries)
+}
+
+async fn read_credential(credential: &Credential) -> Result<String, RunError> {
+    credentials::resolve(credential)
         .await
-        {
-            Ok(_) => (),
-            Err(e) => eprintln!("Error committing to GitHub. Error code: {}", e),
+        .map_err(|e| RunError::from(format!("Credential Error: {}", e)))
+}
+
+/// Settings without a default, which `Config::try_load` already checked for the publisher in use.
+async fn read_required_credential(
+    credential: &Option<Credential>,
+    setting: &str,
+) -> Result<String, RunError> {
+    match credential {
+        Some(credential) => read_credential(credential).await,
+        None => Err(RunError::from(format!(
+            "Config Error: {} is required",
+            setting
+        ))),
+    }
+}
+
+/// Entries of the supported releases after every configured step, refused when too many of
+/// the ones in `old_feed` would disappear. Also returns the oldest release fetched.
+async fn collect_entries(
+    config: &Config,
+    old_feed: &str,
+) -> Result<(Vec<AtomEntry>, u16), RunError> {
+    //Parse latest version
+    let latest_version = get_latest_version().await?;
+    let mut entries = fetch_entries(latest_version - 2..=latest_version).await?;
+    let previous = previous_feed::parse(old_feed);
+
+    if config.signify.enabled || config.patches.diffstat || config.patches.classify {
+        let patch_regex = match Regex::new("href=\"([^\"]+\\.patch\\.sig)\"") {
+            Ok(regex) => regex,
+            Err(e) => {
+                eprintln!("Wrong regex: {}", e);
+                exit(1);
+            }
         };
+        process_patches(&mut entries, config, &patch_regex).await?;
+    }
+    if let Some(ref nvd_feed) = config.nvd.feed {
+        nvd::enrich(&mut entries, &nvd::load_index(nvd_feed)?);
+    }
+    entries.sort_by(AtomEntry::cmp_entries);
+    //Before the severity filter, which drops entries on purpose
+    check_entry_loss(&previous, &entries, config, latest_version - 2)?;
+    if let Some(ref min_severity) = config.nvd.min_severity {
+        let min_severity = match Severity::from_str(min_severity) {
+            Ok(_) if config.nvd.feed.is_none() => {
+                return Err(RunError::from(String::from(
+                    "Config Error: nvd.min_severity needs an nvd.feed file",
+                )))
+            }
+            Ok(severity) => severity,
+            Err(e) => return Err(RunError::from(format!("Config Error: {}", e))),
+        };
+        entries.retain(|e| e.cvss.as_ref().is_some_and(|c| c.severity >= min_severity));
+    }
+    Ok((entries, latest_version - 2))
+}
+
+fn load_templates(config: &Config) -> Result<Tera, RunError> {
+    let extra_templates: Vec<&str> = config
+        .outputs
+        .iter()
+        .map(|o| o.template.as_str())
+        .chain(config.webhooks.iter().filter_map(|w| w.template.as_deref()))
+        .collect();
+    Ok(templates::load(
+        config.templates_dir.as_deref(),
+        &extra_templates,
+    )?)
+}
+
+/// Writes the static site and the extra outputs for `entries`, logging failures.
+fn write_outputs(config: &Config, tera: &Tera, entries: &[AtomEntry]) {
+    if let Some(ref output_dir) = config.site.output_dir {
+        if let Err(e) = site::generate(tera, &config.feed, entries, output_dir) {
+            eprintln!("Error generating static site: {}", e);
+        }
+    }
+    if !config.outputs.is_empty() {
+        let updated = Utc::now().format(ISO_UTC_FORMAT).to_string();
+        let context = templates::feed_context(&config.feed, &updated, entries);
+        for output in &config.outputs {
+            match templates::render(tera, &output.template, &context)
+                .and_then(|out| fs::write(&output.path, out).map_err(|e| e.to_string()))
+            {
+                Ok(_) => println!("Rendered {} to {}", output.template, output.path),
+                Err(e) => eprintln!("Error rendering {}: {}", output.path, e),
+            }
+        }
+    }
+}
+
+async fn publish(config: &Config, publisher: &impl Publisher) -> Result<(), RunError> {
+    //Download latest feed
+    let previous_file = publisher
+        .fetch()
+        .await
+        .map_err(|e| RunError::from(format!("Cannot retrieve previous feed file: {}", e)))?;
+    let (old_feed, sha) = (&previous_file.content, &previous_file.sha);
+
+    let (entries, min_version) = collect_entries(config, old_feed).await?;
+
+    let tera = load_templates(config)?;
+    //Render the feed and checksum for changes
+    let feed = render_feed(&tera, &config.feed, old_feed, &entries, sha)?;
+    if let Some(ref feed) = feed {
+        check_valid(feed)?;
+    }
+    write_outputs(config, &tera, &entries);
+    let Some(mut feed) = feed else {
+        mastodon::announce(&config.mastodon, &entries).await;
+        return Ok(());
+    };
+    //Upload it back, merging in what was published meanwhile on conflicts
+    let mut previous = previous_feed::parse(old_feed);
+    let mut previous_file = previous_file;
+    let mut entries = entries;
+    let mut retries = 0;
+    loop {
+        let changes = changes::diff(&config.feed, &previous, &entries);
+        match config.changes_format {
+            ChangesFormat::Text => println!("{}", changes.to_text()),
+            ChangesFormat::Json => println!("{}", changes.to_json()),
+        }
+        let message = format!("{}\n\n{}", config.commit_message, changes.to_text());
+        match publisher.publish(&feed, &previous_file, &message).await {
+            Ok(()) => {
+                //The feed is already out, so the notifiers only log their failures
+                let new_entries = previous_feed::new_entries(&config.feed, &previous, &entries);
+                websub::notify(&config.feed).await;
+                email::notify(config, &tera, &new_entries).await;
+                webhook::notify(config, &tera, &new_entries).await;
+                mastodon::announce(&config.mastodon, &entries).await;
+                return Ok(());
+            }
+            Err(PublishError::Conflict(e)) if retries < config.conflict_retries => {
+                retries += 1;
+                eprintln!(
+                    "Conflict publishing the feed ({}), merging and retrying ({}/{})",
+                    e, retries, config.conflict_retries
+                );
+                previous_file = publisher.fetch().await.map_err(|e| {
+                    RunError::from(format!("Cannot retrieve current feed file: {}", e))
+                })?;
+                previous = previous_feed::parse(&previous_file.content);
+                for id in previous_feed::merge(&config.feed, &mut entries, &previous, min_version) {
+                    println!("Merged entry {} from the current feed", id);
+                }
+                let Some(merged) = render_feed(
+                    &tera,
+                    &config.feed,
+                    &previous_file.content,
+                    &entries,
+                    &previous_file.sha,
+                )?
+                else {
+                    println!("Current feed is already up to date");
+                    mastodon::announce(&config.mastodon, &entries).await;
+                    return Ok(());
+                };
+                check_valid(&merged)?;
+                feed = merged;
+            }
+            Err(e) => return Err(RunError::from(e).context("Error publishing the feed")),
+        }
+    }
+}
+
+fn check_valid(feed: &str) -> Result<(), RunError> {
+    validate::validate_feed(feed).map_err(|diagnostics| {
+        let mut message =
+            String::from("Validation Error: the new feed is not valid Atom, not publishing it");
+        for diagnostic in diagnostics {
+            message += &format!("\n    {}", diagnostic);
+        }
+        RunError::new(EXIT_INVALID_FEED, message)
+    })
+}
+
+/// Publishes through the backend selected by `config.publisher`.
+async fn publish_configured(config: &Config) -> Result<(), RunError> {
+    match config.publisher {
+        PublisherKind::GitHub => {
+            let github = GitHub {
+                config: &config.github,
+                token: read_credential(&config.github.token).await?,
+            };
+            publish(config, &github).await
+        }
+        PublisherKind::Gitea => {
+            let gitea = Gitea {
+                config: &config.gitea,
+                token: read_required_credential(&config.gitea.token, "gitea.token").await?,
+            };
+            publish(config, &gitea).await
+        }
+        PublisherKind::GitLab => {
+            let gitlab = GitLab {
+                config: &config.gitlab,
+                token: read_required_credential(&config.gitlab.token, "gitlab.token").await?,
+            };
+            publish(config, &gitlab).await
+        }
+        PublisherKind::Git => {
+            publish(
+                config,
+                &LocalGit {
+                    config: &config.git,
+                },
+            )
+            .await
+        }
+        PublisherKind::S3 => {
+            let s3 = S3 {
+                config: &config.s3,
+                secret_key: read_required_credential(&config.s3.secret_key, "s3.secret_key")
+                    .await?,
+            };
+            publish(config, &s3).await
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let config = Config::load(CONFIG_PATH);
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        None => {
+            if let Err(e) = publish_configured(&config).await {
+                e.exit();
+            }
+        }
+        Some("daemon") => daemon::run(config).await,
+        Some("serve") => serve::run(config).await,
+        Some("audit") => audit::run(&args[1..]).await,
+        Some("fleet-report") => fleet::run(&args[1..]).await,
+        Some("cve") => cve::run(&args[1..]).await,
+        Some(command) => {
+            eprintln!("Unknown command: {}\n{}", command, USAGE);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_entry_loss, EXIT_ENTRY_LOSS};
+    use crate::{atom_entry::AtomEntry, config::Config, previous_feed::PreviousEntry};
+    use chrono::DateTime;
+
+    fn id(version: u16, number: usize) -> String {
+        format!("v{}-p{:03}_x", version, number)
+    }
+
+    fn entry(version: u16, number: usize) -> AtomEntry {
+        AtomEntry {
+            id: id(version, number),
+            kind: String::from("security fix"),
+            title: format!("{:03}: security fix", number),
+            updated: DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").unwrap(),
+            link: String::new(),
+            content: String::new(),
+            release_version: version,
+            iteration_count: number - 1,
+            patch_url: None,
+            signature: None,
+            files: Vec::new(),
+            impact: None,
+            cves: Vec::new(),
+            advisories: Vec::new(),
+            cvss: None,
+        }
+    }
+
+    fn previous(
+        config: &Config,
+        version: u16,
+        numbers: impl Iterator<Item = usize>,
+    ) -> Vec<PreviousEntry> {
+        numbers
+            .map(|number| PreviousEntry {
+                id: format!("{}/{}", config.feed.id_prefix, id(version, number)),
+                title: String::new(),
+                updated: String::new(),
+                content: String::new(),
+                link: String::new(),
+                advisories: Vec::new(),
+                impact: None,
+                cves: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn entries(version: u16, numbers: impl Iterator<Item = usize>) -> Vec<AtomEntry> {
+        numbers.map(|number| entry(version, number)).collect()
+    }
+
+    #[test]
+    fn allows_removals_up_to_the_threshold() {
+        let config = Config::default();
+        //1 of 10 is exactly the default 10%
+        let old = previous(&config, 74, 1..=10);
+        assert!(check_entry_loss(&old, &entries(74, 2..=10), &config, 73).is_ok());
+    }
+
+    #[test]
+    fn refuses_removals_over_the_threshold() {
+        let config = Config::default();
+        let old = previous(&config, 74, 1..=10);
+        let e = check_entry_loss(&old, &entries(74, 3..=10), &config, 73).unwrap_err();
+        assert_eq!(e.code, EXIT_ENTRY_LOSS);
+        assert!(e.message.contains("2 of 10 entries (20%)"), "{}", e.message);
+        assert!(e.message.contains("v74-p001_x") && e.message.contains("v74-p002_x"));
+    }
+
+    #[test]
+    fn ignores_releases_no_longer_fetched() {
+        let config = Config::default();
+        let mut old = previous(&config, 72, 1..=20);
+        old.extend(previous(&config, 74, 1..=5));
+        assert!(check_entry_loss(&old, &entries(74, 1..=5), &config, 73).is_ok());
+    }
+
+    #[test]
+    fn skips_the_first_run() {
+        let config = Config::default();
+        assert!(check_entry_loss(&[], &entries(74, 1..=5), &config, 73).is_ok());
+        assert!(check_entry_loss(&[], &[], &config, 73).is_ok());
     }
 }
@@ -0,0 +1,166 @@
+use crate::{atom_entry::AtomEntry, config::MastodonConfig, credentials, release_name};
+use regex::Regex;
+use reqwest::{header, Client};
+use serde_json::json;
+use std::{fs, io::ErrorKind};
+
+/// Mastodon counts every link as this long, whatever its length.
+const URL_CHARS: usize = 23;
+
+fn truncate(text: &str, max: usize) -> String {
+    match text.chars().count() <= max {
+        true => text.to_string(),
+        false => {
+            let cut: String = text.chars().take(max.saturating_sub(1)).collect();
+            let cut = cut
+                .rsplit_once(' ')
+                .map_or(cut.as_str(), |(words, _)| words);
+            format!("{}…", cut.trim_end())
+        }
+    }
+}
+
+/// The erratum text without markup, on a single line.
+fn summary(content: &str) -> Result<String, String> {
+    let tags = Regex::new("<[^>]*>").map_err(|e| format!("Wrong regex: {}", e))?;
+    let text = html_escape::decode_html_entities(&tags.replace_all(content, " ")).to_string();
+    Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Status text and content warning of an entry: its title, as much of its text as fits in
+/// `max_chars`, and its link.
+fn status(config: &MastodonConfig, entry: &AtomEntry) -> Result<(String, Option<String>), String> {
+    let spoiler =
+        (config.content_warning && entry.kind.eq_ignore_ascii_case("security fix")).then(|| {
+            format!(
+                "OpenBSD {} security fix",
+                release_name(entry.release_version)
+            )
+        });
+    let fixed = spoiler.as_ref().map_or(0, |s| s.chars().count()) + URL_CHARS + 2;
+    let title = truncate(&entry.title, config.max_chars.saturating_sub(fixed));
+    let room = config
+        .max_chars
+        .saturating_sub(fixed + title.chars().count() + 2);
+    let summary = summary(&entry.content)?;
+    //A few words cut short say nothing
+    let text = match room >= 40 && !summary.is_empty() {
+        true => format!(
+            "{}\n\n{}\n\n{}",
+            title,
+            truncate(&summary, room),
+            entry.link
+        ),
+        false => format!("{}\n\n{}", title, entry.link),
+    };
+    Ok((text, spoiler))
+}
+
+/// Ids of the entries already handled, `None` before the first run.
+fn load_state(path: &str) -> Result<Option<Vec<String>>, String> {
+    match fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| format!("Error parsing {}: {}", path, e)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Error reading {}: {}", path, e)),
+    }
+}
+
+/// Written aside and renamed, so an interrupted run can't leave a truncated file behind.
+fn save_state(path: &str, ids: &[String]) -> Result<(), String> {
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, json!(ids).to_string())
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| format!("Error writing {}: {}", path, e))
+}
+
+async fn post(
+    config: &MastodonConfig,
+    instance: &str,
+    token: &str,
+    entry: &AtomEntry,
+) -> Result<(), String> {
+    let (text, spoiler) = status(config, entry)?;
+    let mut body = json!({
+        "status": text,
+        "visibility": config.visibility,
+        "language": "en",
+    });
+    if let Some(spoiler) = spoiler {
+        body["spoiler_text"] = json!(spoiler);
+    }
+    let res = Client::new()
+        .post(format!(
+            "{}/api/v1/statuses",
+            instance.trim_end_matches('/')
+        ))
+        .bearer_auth(token)
+        //Makes the instance ignore a repeated post of the same entry
+        .header("Idempotency-Key", format!("syspatch-feed-{}", entry.id))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .map_err(|e| format!("Error posting status: {}", e))?;
+    match res.status().is_success() {
+        true => Ok(()),
+        false => Err(format!(
+            "Server Error: {}, Response Body: {:?}",
+            res.status(),
+            res.text().await.unwrap_or_default()
+        )),
+    }
+}
+
+async fn run(config: &MastodonConfig, instance: &str, entries: &[AtomEntry]) -> Result<(), String> {
+    let mut handled = match load_state(&config.state_file)? {
+        Some(ids) => ids,
+        None => {
+            //Without it every entry of the feed would be posted at once
+            let ids: Vec<String> = entries.iter().map(|e| e.id.clone()).collect();
+            save_state(&config.state_file, &ids)?;
+            println!(
+                "Mastodon: recorded the {} current entries in {}, only later ones will be posted",
+                ids.len(),
+                config.state_file
+            );
+            return Ok(());
+        }
+    };
+    //Oldest first, so they show up in order on the timeline
+    let (pending, skipped): (Vec<&AtomEntry>, Vec<&AtomEntry>) = entries
+        .iter()
+        .rev()
+        .filter(|e| !handled.contains(&e.id))
+        .partition(|e| e.matches(&config.releases, &config.kinds));
+    //Entries filtered out stay so, even if the filters change later
+    if !skipped.is_empty() {
+        handled.extend(skipped.iter().map(|e| e.id.clone()));
+        save_state(&config.state_file, &handled)?;
+    }
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let token = credentials::resolve(&config.token)
+        .await
+        .map_err(|e| format!("Credential Error: {}", e))?;
+    for entry in pending {
+        post(config, instance, &token, entry).await?;
+        handled.push(entry.id.clone());
+        save_state(&config.state_file, &handled)?;
+        println!("Announced {} on {}", entry.id, instance);
+    }
+    Ok(())
+}
+
+/// Posts the entries not announced yet. After a failure the entries left are posted on the
+/// next run.
+pub async fn announce(config: &MastodonConfig, entries: &[AtomEntry]) {
+    if let Some(ref instance) = config.instance {
+        if let Err(e) = run(config, instance, entries).await {
+            eprintln!("Mastodon Error: {}", e);
+        }
+    }
+}
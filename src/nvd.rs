@@ -0,0 +1,255 @@
+use crate::atom_entry::AtomEntry;
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, fs, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    None,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Severity, String> {
+        match s.to_ascii_uppercase().as_str() {
+            "NONE" => Ok(Severity::None),
+            "LOW" => Ok(Severity::Low),
+            "MEDIUM" => Ok(Severity::Medium),
+            "HIGH" => Ok(Severity::High),
+            "CRITICAL" => Ok(Severity::Critical),
+            _ => Err(format!("unknown severity: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::None => "NONE",
+            Severity::Low => "LOW",
+            Severity::Medium => "MEDIUM",
+            Severity::High => "HIGH",
+            Severity::Critical => "CRITICAL",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cvss {
+    pub cve: String,
+    pub score: f32,
+    pub severity: Severity,
+    pub vector: String,
+}
+
+//Subset of the NVD CVE API 2.0 JSON schema
+#[derive(Deserialize)]
+struct NvdFeed {
+    vulnerabilities: Vec<NvdVulnerability>,
+}
+
+#[derive(Deserialize)]
+struct NvdVulnerability {
+    cve: NvdCve,
+}
+
+#[derive(Deserialize)]
+struct NvdCve {
+    id: String,
+    #[serde(default)]
+    metrics: NvdMetrics,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct NvdMetrics {
+    cvss_metric_v40: Vec<NvdMetric>,
+    cvss_metric_v31: Vec<NvdMetric>,
+    cvss_metric_v30: Vec<NvdMetric>,
+    cvss_metric_v2: Vec<NvdMetric>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NvdMetric {
+    #[serde(rename = "type", default)]
+    metric_type: String,
+    cvss_data: CvssData,
+    //CVSS v2 keeps it outside of cvssData
+    base_severity: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CvssData {
+    base_score: f32,
+    base_severity: Option<String>,
+    vector_string: String,
+}
+
+impl NvdMetrics {
+    /// Newest CVSS version first, preferring the NVD's own "Primary" score.
+    fn best(&self) -> Option<&NvdMetric> {
+        [
+            &self.cvss_metric_v40,
+            &self.cvss_metric_v31,
+            &self.cvss_metric_v30,
+            &self.cvss_metric_v2,
+        ]
+        .iter()
+        .find_map(|metrics| {
+            metrics
+                .iter()
+                .find(|m| m.metric_type == "Primary")
+                .or(metrics.first())
+        })
+    }
+}
+
+/// Loads a locally downloaded NVD JSON 2.0 data file, keyed by CVE id.
+pub fn load_index(path: &str) -> Result<HashMap<String, Cvss>, String> {
+    let json =
+        fs::read_to_string(path).map_err(|e| format!("Error reading NVD feed {}: {}", path, e))?;
+    let feed: NvdFeed = serde_json::from_str(&json)
+        .map_err(|e| format!("NVD Feed Parse Error in {}: {}", path, e))?;
+    let mut idx = HashMap::<String, Cvss>::new();
+    for vulnerability in feed.vulnerabilities {
+        let metric = match vulnerability.cve.metrics.best() {
+            Some(metric) => metric,
+            None => continue,
+        };
+        let severity = metric
+            .cvss_data
+            .base_severity
+            .as_ref()
+            .or(metric.base_severity.as_ref())
+            .and_then(|s| Severity::from_str(s).ok())
+            .unwrap_or(Severity::None);
+        idx.insert(
+            vulnerability.cve.id.clone(),
+            Cvss {
+                cve: vulnerability.cve.id,
+                score: metric.cvss_data.base_score,
+                severity,
+                vector: metric.cvss_data.vector_string.clone(),
+            },
+        );
+    }
+    Ok(idx)
+}
+
+/// Attaches the highest scored CVE of each entry and prefixes its title with the severity.
+pub fn enrich(entries: &mut [AtomEntry], idx: &HashMap<String, Cvss>) {
+    for entry in entries.iter_mut() {
+        entry.cvss = entry
+            .cves
+            .iter()
+            .filter_map(|cve| idx.get(cve))
+            .max_by(|a, b| a.score.total_cmp(&b.score))
+            .cloned();
+        if let Some(ref cvss) = entry.cvss {
+            entry.title = format!("[{}] {}", cvss.severity, entry.title);
+            entry.content += &format!(
+                "<p><b>CVSS:</b> {:.1} {} <code>{}</code> ({})</p>",
+                cvss.score,
+                cvss.severity,
+                html_escape::encode_text(&cvss.vector),
+                cvss.cve
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NvdFeed;
+
+    //Trimmed from a record of the NVD CVE API 2.0
+    const RECORD: &str = r#"{
+      "vulnerabilities": [{
+        "cve": {
+          "id": "CVE-2023-38408",
+          "metrics": {
+            "cvssMetricV31": [
+              {
+                "source": "cna@example.com",
+                "type": "Secondary",
+                "cvssData": {
+                  "version": "3.1",
+                  "vectorString": "CVSS:3.1/AV:N/AC:H/PR:N/UI:N/S:U/C:H/I:H/A:H",
+                  "baseScore": 8.1,
+                  "baseSeverity": "HIGH"
+                }
+              },
+              {
+                "source": "nvd@nist.gov",
+                "type": "Primary",
+                "cvssData": {
+                  "version": "3.1",
+                  "vectorString": "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H",
+                  "baseScore": 9.8,
+                  "baseSeverity": "CRITICAL"
+                }
+              }
+            ],
+            "cvssMetricV2": [{
+              "source": "nvd@nist.gov",
+              "type": "Primary",
+              "cvssData": {
+                "version": "2.0",
+                "vectorString": "AV:N/AC:L/Au:N/C:P/I:P/A:P",
+                "baseScore": 7.5
+              },
+              "baseSeverity": "HIGH"
+            }]
+          }
+        }
+      }]
+    }"#;
+
+    fn feed() -> NvdFeed {
+        serde_json::from_str(RECORD).unwrap()
+    }
+
+    #[test]
+    fn prefers_primary_score_of_newest_version() {
+        let feed = feed();
+        let best = feed.vulnerabilities[0].cve.metrics.best().unwrap();
+        assert_eq!(best.cvss_data.base_score, 9.8);
+    }
+
+    #[test]
+    fn prefers_v40_over_v31() {
+        let mut feed = feed();
+        let metrics = &mut feed.vulnerabilities[0].cve.metrics;
+        let v40 = serde_json::from_str(
+            r#"{
+              "source": "cna@example.com",
+              "type": "Secondary",
+              "cvssData": {
+                "version": "4.0",
+                "vectorString": "CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N/VC:H/VI:H/VA:H/SC:N/SI:N/SA:N",
+                "baseScore": 9.3,
+                "baseSeverity": "CRITICAL"
+              }
+            }"#,
+        )
+        .unwrap();
+        metrics.cvss_metric_v40.push(v40);
+        assert_eq!(metrics.best().unwrap().cvss_data.base_score, 9.3);
+    }
+
+    #[test]
+    fn falls_back_to_v2() {
+        let mut feed = feed();
+        let metrics = &mut feed.vulnerabilities[0].cve.metrics;
+        metrics.cvss_metric_v31.clear();
+        let best = metrics.best().unwrap();
+        assert_eq!(best.cvss_data.base_score, 7.5);
+        assert_eq!(best.base_severity.as_deref(), Some("HIGH"));
+    }
+}
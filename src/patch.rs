@@ -0,0 +1,328 @@
+use crate::{
+    html::{http_get, HttpError},
+    signify,
+};
+use std::{fs, path::PathBuf};
+
+#[derive(Debug)]
+pub struct FileStat {
+    pub path: String,
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// Keeps downloaded `.patch.sig` files on disk so each patch is only fetched once. Only files
+/// in the signify format are kept, a bogus response is fetched again on the next run.
+#[derive(Debug)]
+pub struct PatchCache {
+    dir: Option<PathBuf>,
+}
+
+impl PatchCache {
+    pub fn new(dir: &str) -> PatchCache {
+        match fs::create_dir_all(dir) {
+            Ok(_) => PatchCache {
+                dir: Some(PathBuf::from(dir)),
+            },
+            Err(e) => {
+                eprintln!("Cannot use patch cache in {}: {}", dir, e);
+                PatchCache { dir: None }
+            }
+        }
+    }
+
+    fn cache_path(&self, url: &str) -> Option<PathBuf> {
+        let name: String = url[url.find("://").map_or(0, |i| i + 3)..]
+            .chars()
+            .map(|c| match c {
+                'A'..='Z' | 'a'..='z' | '0'..='9' | '.' | '-' | '_' => c,
+                _ => '_',
+            })
+            .collect();
+        self.dir.as_ref().map(|dir| dir.join(name))
+    }
+
+    /// `Ok(None)` when the patch is not on the server.
+    pub async fn get(&self, url: &str) -> Result<Option<String>, HttpError> {
+        let path = self.cache_path(url);
+        if let Some(patch) = path.as_ref().and_then(|p| fs::read_to_string(p).ok()) {
+            if signify::is_embedded_signature(patch.as_bytes()) {
+                return Ok(Some(patch));
+            }
+        }
+        let patch = match http_get(url, false).await {
+            Ok((patch, _)) => patch,
+            Err(HttpError::NotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        match path {
+            Some(path) if signify::is_embedded_signature(patch.as_bytes()) => {
+                //Renamed into place so an interrupted write doesn't leave half a file
+                let tmp = path.with_extension("tmp");
+                if let Err(e) = fs::write(&tmp, &patch).and_then(|_| fs::rename(&tmp, &path)) {
+                    eprintln!("Error caching patch {}: {}", path.display(), e);
+                }
+            }
+            Some(_) => eprintln!("Not caching {}: not a signify signed file", url),
+            None => (),
+        }
+        Ok(Some(patch))
+    }
+}
+
+fn hunk_lengths(header: &str) -> Option<(usize, usize)> {
+    let mut ranges = header.strip_prefix("@@ -")?.split(' ');
+    let length = |range: &str| {
+        range
+            .split_once(',')
+            .map_or(Some(1), |(_, l)| l.parse().ok())
+    };
+    let old = length(ranges.next()?)?;
+    let new = length(ranges.next()?.strip_prefix('+')?)?;
+    Some((old, new))
+}
+
+/// Counts added and removed lines per file of a unified diff.
+pub fn diffstat(patch: &str) -> Vec<FileStat> {
+    let mut stats = Vec::<FileStat>::new();
+    let mut lines = patch.lines();
+    while let Some(line) = lines.next() {
+        if let Some(path) = line.strip_prefix("+++ ") {
+            let path = path.split('\t').next().unwrap_or(path).trim();
+            stats.push(FileStat {
+                path: path.strip_prefix("b/").unwrap_or(path).to_string(),
+                added: 0,
+                removed: 0,
+            });
+        } else if let (Some((mut old, mut new)), Some(stat)) =
+            (hunk_lengths(line), stats.last_mut())
+        {
+            while old > 0 || new > 0 {
+                match lines.next() {
+                    Some(l) if l.starts_with('+') => {
+                        stat.added += 1;
+                        new = new.saturating_sub(1);
+                    }
+                    Some(l) if l.starts_with('-') => {
+                        stat.removed += 1;
+                        old = old.saturating_sub(1);
+                    }
+                    Some(l) if l.starts_with('\\') => (),
+                    Some(_) => {
+                        old = old.saturating_sub(1);
+                        new = new.saturating_sub(1);
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+    stats
+}
+
+pub fn render_diffstat(stats: &[FileStat]) -> String {
+    let mut html = String::from("<p><b>Files:</b></p><ul>");
+    for stat in stats {
+        html += &format!(
+            "<li><code>{}</code> +{} -{}</li>",
+            html_escape::encode_text(&stat.path),
+            stat.added,
+            stat.removed
+        );
+    }
+    html + "</ul>"
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Impact {
+    Kernel,
+    Library(Vec<String>),
+    Userland(Vec<String>),
+}
+
+impl Impact {
+    pub fn term(&self) -> &'static str {
+        match self {
+            Impact::Kernel => "kernel",
+            Impact::Library(_) => "library",
+            Impact::Userland(_) => "userland",
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Impact::Kernel => String::from("kernel – reboot required"),
+            Impact::Library(libs) if libs.is_empty() => {
+                String::from("library – restart linked daemons")
+            }
+            Impact::Library(libs) => {
+                format!("library – restart daemons linked to {}", libs.join(", "))
+            }
+            Impact::Userland(programs) if programs.is_empty() => {
+                String::from("userland – restart the affected service")
+            }
+            Impact::Userland(programs) => format!("userland – restart {}", programs.join(", ")),
+        }
+    }
+
+    /// Reads back the `<category>` written with `term` and `label`.
+    pub fn from_category(term: &str, label: &str) -> Option<Impact> {
+        let names = |rest: Option<&str>| {
+            rest.map_or_else(Vec::new, |names| {
+                names.split(", ").map(String::from).collect()
+            })
+        };
+        match term {
+            "kernel" => Some(Impact::Kernel),
+            "library" => Some(Impact::Library(names(
+                label.strip_prefix("library – restart daemons linked to "),
+            ))),
+            "userland" if label == "userland – restart the affected service" => {
+                Some(Impact::Userland(Vec::new()))
+            }
+            "userland" => Some(Impact::Userland(names(
+                label.strip_prefix("userland – restart "),
+            ))),
+            _ => None,
+        }
+    }
+}
+
+/// Second path component after one of `prefixes`, e.g. `smtpd` for `usr.sbin/smtpd/smtp.c`.
+fn component_after<'a>(path: &'a str, prefixes: &[&str]) -> Option<&'a str> {
+    prefixes.iter().find_map(|prefix| {
+        path.strip_prefix(prefix)
+            .and_then(|rest| rest.split('/').next())
+            .filter(|name| !name.is_empty())
+    })
+}
+
+/// Kernel changes need a reboot, libraries a restart of whatever links them, anything else
+/// just the programs it touches.
+pub fn classify(files: &[FileStat]) -> Option<Impact> {
+    if files.is_empty() {
+        return None;
+    }
+    if files.iter().any(|f| f.path.starts_with("sys/")) {
+        return Some(Impact::Kernel);
+    }
+    let mut libs = Vec::<String>::new();
+    let mut programs = Vec::<String>::new();
+    for file in files {
+        if let Some(lib) = component_after(&file.path, &["lib/", "gnu/lib/"]) {
+            if !libs.iter().any(|l| l == lib) {
+                libs.push(lib.to_string());
+            }
+        } else if let Some(program) = component_after(
+            &file.path,
+            &[
+                "bin/",
+                "sbin/",
+                "usr.bin/",
+                "usr.sbin/",
+                "libexec/",
+                "gnu/usr.bin/",
+                "gnu/usr.sbin/",
+            ],
+        ) {
+            if !programs.iter().any(|p| p == program) {
+                programs.push(program.to_string());
+            }
+        }
+    }
+    match libs.is_empty() {
+        false => Some(Impact::Library(libs)),
+        true => Some(Impact::Userland(programs)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, diffstat, FileStat, Impact};
+
+    const PATCH: &str = "untrusted comment: verify with openbsd-74-base.pub
+RWRoyQmAD08ajTwz/L/9ib0Ex4Q1WUj8QaA6rqW6aIRuRGzDIZQGb+C8MH9ocU+xE9Hc0jezz7NeqqOTYvFZYiHpJlnnUzOYPQ8=
+
+OpenBSD 7.4 errata 001, January 2, 2024:
+
+Apply by doing:
+    signify -Vep /etc/signify/openbsd-74-base.pub -x 001_gcc.patch.sig \\
+        -m - | (cd /usr/src && patch -p0)
+
+Index: gnu/lib/libstdc++/src/string.cc
+--- gnu/lib/libstdc++/src/string.cc.orig\t1 Jan 2024
++++ gnu/lib/libstdc++/src/string.cc\t2 Jan 2024
+@@ -10,5 +10,6 @@ namespace std
+ context
+-removed
++added one
++added two
+ context
+-- not a removal marker in a hunk, counted as removed
++++ not a file header in a hunk, counted as added
+ context
+\\ No newline at end of file
+Index: usr.sbin/smtpd/smtp.c
+--- usr.sbin/smtpd/smtp.c.orig
++++ usr.sbin/smtpd/smtp.c
+@@ -1 +1 @@
+-old
++new
+";
+
+    fn stat(path: &str) -> FileStat {
+        FileStat {
+            path: path.to_string(),
+            added: 1,
+            removed: 1,
+        }
+    }
+
+    #[test]
+    fn counts_lines_per_file() {
+        let stats = diffstat(PATCH);
+        let counts: Vec<(&str, usize, usize)> = stats
+            .iter()
+            .map(|s| (s.path.as_str(), s.added, s.removed))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                ("gnu/lib/libstdc++/src/string.cc", 3, 2),
+                ("usr.sbin/smtpd/smtp.c", 1, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn strips_git_prefix() {
+        let stats = diffstat("--- a/lib/libc/x.c\n+++ b/lib/libc/x.c\n@@ -1 +1 @@\n-a\n+b\n");
+        assert_eq!(stats[0].path, "lib/libc/x.c");
+    }
+
+    #[test]
+    fn classifies_gnu_lib_as_library() {
+        let stats = diffstat(PATCH);
+        assert_eq!(
+            classify(&stats),
+            Some(Impact::Library(vec![String::from("libstdc++")]))
+        );
+    }
+
+    #[test]
+    fn classifies_kernel_first() {
+        assert_eq!(
+            classify(&[stat("lib/libc/x.c"), stat("sys/kern/x.c")]),
+            Some(Impact::Kernel)
+        );
+    }
+
+    #[test]
+    fn classifies_programs() {
+        assert_eq!(
+            classify(&[stat("usr.sbin/smtpd/smtp.c"), stat("usr.sbin/smtpd/mta.c")]),
+            Some(Impact::Userland(vec![String::from("smtpd")]))
+        );
+        assert_eq!(classify(&[]), None);
+    }
+}
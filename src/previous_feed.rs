@@ -0,0 +1,266 @@
+use crate::{
+    atom_entry::AtomEntry, config::FeedConfig, get_kind, patch::Impact, validate::atom_children,
+};
+use chrono::DateTime;
+use roxmltree::{Document, Node};
+
+/// An entry of the feed currently published, as written by the templates.
+#[derive(Debug, Clone)]
+pub struct PreviousEntry {
+    pub id: String,
+    pub title: String,
+    pub updated: String,
+    pub content: String,
+    pub link: String,
+    pub advisories: Vec<String>,
+    pub impact: Option<Impact>,
+    pub cves: Vec<String>,
+}
+
+fn child_text(node: Node, name: &str) -> String {
+    atom_children(node, name)
+        .first()
+        .and_then(|c| c.text())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// `href` of the `rel` links of `node`.
+fn links(node: Node, rel: &str) -> Vec<String> {
+    atom_children(node, "link")
+        .iter()
+        .filter(|l| l.attribute("rel").unwrap_or("alternate") == rel)
+        .filter_map(|l| l.attribute("href"))
+        .map(String::from)
+        .collect()
+}
+
+fn previous_entry(entry: Node) -> PreviousEntry {
+    let categories = atom_children(entry, "category");
+    PreviousEntry {
+        id: child_text(entry, "id"),
+        title: child_text(entry, "title"),
+        updated: child_text(entry, "updated"),
+        content: child_text(entry, "content"),
+        link: links(entry, "alternate")
+            .into_iter()
+            .next()
+            .unwrap_or_default(),
+        advisories: links(entry, "related"),
+        impact: categories
+            .iter()
+            .find_map(|c| Impact::from_category(c.attribute("term")?, c.attribute("label")?)),
+        cves: categories
+            .iter()
+            .filter_map(|c| c.attribute("term"))
+            .filter(|term| term.starts_with("CVE-"))
+            .map(String::from)
+            .collect(),
+    }
+}
+
+/// An empty or unparseable feed has no entries.
+pub fn parse(xml: &str) -> Vec<PreviousEntry> {
+    let doc = match Document::parse(xml) {
+        Ok(doc) => doc,
+        Err(e) => {
+            if !xml.trim().is_empty() {
+                eprintln!("Cannot parse previous feed: {}", e);
+            }
+            return Vec::new();
+        }
+    };
+    atom_children(doc.root_element(), "entry")
+        .into_iter()
+        .map(previous_entry)
+        .collect()
+}
+
+/// Entries not in the `previous` feed, the ones to announce. Nothing is new on the first
+/// publish, when there is no previous feed, so nobody gets every erratum at once.
+pub fn new_entries<'a>(
+    feed: &FeedConfig,
+    previous: &[PreviousEntry],
+    entries: &'a [AtomEntry],
+) -> Vec<&'a AtomEntry> {
+    if previous.is_empty() {
+        return Vec::new();
+    }
+    entries
+        .iter()
+        .filter(|e| {
+            let id = format!("{}/{}", feed.id_prefix, e.id);
+            !previous.iter().any(|p| p.id == id)
+        })
+        .collect()
+}
+
+/// Release an entry id like `tag:...:syspatch_feed/v72-p001_x509` belongs to.
+pub fn release_version(id: &str) -> Option<u16> {
+    let id = &id[id.rfind('/').map_or(0, |i| i + 1)..];
+    id.strip_prefix('v')?.split('-').next()?.parse().ok()
+}
+
+impl PreviousEntry {
+    /// The entry as fetched again, for the ones another run published. What the feed doesn't
+    /// keep, like the signature check or the CVSS score, is left empty; `None` when the entry
+    /// isn't one of ours.
+    fn to_atom_entry(&self, feed: &FeedConfig) -> Option<AtomEntry> {
+        let id = self
+            .id
+            .strip_prefix(&feed.id_prefix)?
+            .strip_prefix('/')?
+            .to_string();
+        //Patches are numbered in the order of the errata page
+        let number: String = id
+            .split_once('-')?
+            .1
+            .chars()
+            .skip_while(|c| !c.is_ascii_digit())
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        Some(AtomEntry {
+            kind: get_kind(&self.title),
+            title: self.title.clone(),
+            updated: DateTime::parse_from_rfc3339(&self.updated).ok()?,
+            link: self.link.clone(),
+            content: self.content.clone(),
+            release_version: release_version(&id)?,
+            iteration_count: number.parse::<usize>().map_or(0, |n| n.saturating_sub(1)),
+            patch_url: None,
+            signature: None,
+            files: Vec::new(),
+            impact: self.impact.clone(),
+            cves: self.cves.clone(),
+            advisories: self.advisories.clone(),
+            cvss: None,
+            id,
+        })
+    }
+}
+
+/// Adds to `entries` the ones only found in `current`, the feed published meanwhile by
+/// somebody else, leaving out releases older than `min_version`. Entries in both keep our
+/// version. Returns the ids taken from `current`.
+pub fn merge(
+    feed: &FeedConfig,
+    entries: &mut Vec<AtomEntry>,
+    current: &[PreviousEntry],
+    min_version: u16,
+) -> Vec<String> {
+    let theirs: Vec<AtomEntry> = current
+        .iter()
+        .filter_map(|p| p.to_atom_entry(feed))
+        .filter(|t| t.release_version >= min_version)
+        .filter(|t| !entries.iter().any(|e| e.id == t.id))
+        .collect();
+    let merged_ids = theirs.iter().map(|t| t.id.clone()).collect();
+    entries.extend(theirs);
+    entries.sort_by(AtomEntry::cmp_entries);
+    merged_ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, parse};
+    use crate::{atom_entry::AtomEntry, config::FeedConfig, patch::Impact, templates};
+    use chrono::DateTime;
+
+    const UPDATED: &str = "2024-03-01T00:00:00Z";
+
+    fn entry(version: u16, number: usize, date: &str) -> AtomEntry {
+        let id = format!("v{}-p{:03}_x", version, number);
+        AtomEntry {
+            kind: String::from("security fix"),
+            title: format!("{:03}: security fix", number),
+            updated: DateTime::parse_from_rfc3339(&format!("{}T00:00:00Z", date)).unwrap(),
+            link: format!(
+                "https://www.openbsd.org/errata{}.html#p{:03}_x",
+                version, number
+            ),
+            content: String::from("<p>Fix & test</p>"),
+            release_version: version,
+            iteration_count: number - 1,
+            patch_url: None,
+            signature: None,
+            files: Vec::new(),
+            impact: None,
+            cves: Vec::new(),
+            advisories: Vec::new(),
+            cvss: None,
+            id,
+        }
+    }
+
+    fn render(entries: &[AtomEntry]) -> String {
+        let tera = templates::load(None, &[]).unwrap();
+        let context = templates::feed_context(&FeedConfig::default(), UPDATED, entries);
+        templates::render(&tera, templates::FEED_TEMPLATE, &context).unwrap()
+    }
+
+    fn ids(entries: &[AtomEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[test]
+    fn renders_merged_entries_as_published() {
+        let mut first = entry(74, 2, "2024-02-01");
+        first.impact = Some(Impact::Library(vec![
+            String::from("libc"),
+            String::from("libssl"),
+        ]));
+        first.cves = vec![String::from("CVE-2024-0001")];
+        first.advisories = vec![String::from("https://example.com/advisory?a=1&b=2")];
+        let mut second = entry(74, 1, "2024-01-01");
+        second.impact = Some(Impact::Userland(Vec::new()));
+        let published = render(&[first, second]);
+
+        let mut entries = Vec::new();
+        let merged = merge(&FeedConfig::default(), &mut entries, &parse(&published), 74);
+        assert_eq!(merged, ["v74-p002_x", "v74-p001_x"]);
+        assert_eq!(render(&entries), published);
+    }
+
+    #[test]
+    fn keeps_our_entries_and_orders_by_date() {
+        let mut theirs = entry(74, 1, "2024-01-01");
+        theirs.title = String::from("001: their title");
+        let current = parse(&render(&[
+            entry(74, 3, "2024-03-01"),
+            entry(74, 2, "2024-02-01"),
+            theirs,
+        ]));
+
+        let mut entries = vec![entry(74, 2, "2024-02-01"), entry(74, 1, "2024-01-01")];
+        let merged = merge(&FeedConfig::default(), &mut entries, &current, 74);
+        assert_eq!(merged, ["v74-p003_x"]);
+        assert_eq!(ids(&entries), ["v74-p003_x", "v74-p002_x", "v74-p001_x"]);
+        assert_eq!(entries[2].title, "001: security fix");
+    }
+
+    #[test]
+    fn leaves_out_unsupported_releases() {
+        let current = parse(&render(&[
+            entry(74, 1, "2024-01-01"),
+            entry(72, 9, "2023-12-01"),
+        ]));
+        let mut entries = Vec::new();
+        let merged = merge(&FeedConfig::default(), &mut entries, &current, 73);
+        assert_eq!(merged, ["v74-p001_x"]);
+        assert_eq!(ids(&entries), ["v74-p001_x"]);
+    }
+
+    #[test]
+    fn merges_nothing_from_an_empty_feed() {
+        let mut entries = vec![entry(74, 1, "2024-01-01")];
+        assert!(merge(
+            &FeedConfig::default(),
+            &mut entries,
+            &parse(&render(&[])),
+            74
+        )
+        .is_empty());
+        assert!(merge(&FeedConfig::default(), &mut entries, &parse(""), 74).is_empty());
+        assert_eq!(ids(&entries), ["v74-p001_x"]);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::{
+    config::{GitConfig, GitHubConfig, GitLabConfig, GiteaConfig},
+    git,
+    html::{calc_git_sha1, github_commit, github_get},
+    GITHUB_REPO_OWNER,
+};
+use base64::Engine;
+use reqwest::{header, Client, Method, RequestBuilder, Url};
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Feed file currently published. `revision` is what the backend needs to update it without
+/// overwriting somebody else's change: the blob SHA, or the last commit id on GitLab. It is
+/// empty when the file doesn't exist yet and has to be created.
+#[derive(Debug)]
+pub struct FeedFile {
+    pub content: String,
+    pub sha: String,
+    pub revision: String,
+}
+
+impl FeedFile {
+    pub fn new_file() -> FeedFile {
+        FeedFile {
+            content: String::new(),
+            sha: String::new(),
+            revision: String::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PublishError {
+    /// The file changed since it was fetched, fetching it again and retrying can succeed.
+    Conflict(String),
+    /// Unexpected HTTP status.
+    Server(String),
+    Failed(String),
+}
+
+impl fmt::Display for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PublishError::Conflict(e) | PublishError::Server(e) | PublishError::Failed(e) => {
+                f.write_str(e)
+            }
+        }
+    }
+}
+
+impl From<String> for PublishError {
+    fn from(e: String) -> PublishError {
+        PublishError::Failed(e)
+    }
+}
+
+pub trait Publisher {
+    async fn fetch(&self) -> Result<FeedFile, String>;
+    async fn publish(
+        &self,
+        feed: &str,
+        previous: &FeedFile,
+        message: &str,
+    ) -> Result<(), PublishError>;
+}
+
+fn api_url(base: &str, segments: &[&str]) -> Result<Url, String> {
+    let mut url = Url::parse(base).map_err(|e| format!("invalid API URL {}: {}", base, e))?;
+    match url.path_segments_mut() {
+        Ok(mut path) => {
+            path.pop_if_empty().extend(segments);
+        }
+        Err(_) => return Err(format!("invalid API URL {}", base)),
+    }
+    Ok(url)
+}
+
+async fn send(request: RequestBuilder) -> Result<(u16, Value), String> {
+    let res = request
+        .header(header::USER_AGENT, GITHUB_REPO_OWNER)
+        .send()
+        .await
+        .map_err(|e| format!("request failed: {}", e))?;
+    let status = res.status().as_u16();
+    let body = res
+        .text()
+        .await
+        .map_err(|e| format!("error receiving response: {}", e))?;
+    Ok((
+        status,
+        serde_json::from_str(&body).unwrap_or(Value::String(body)),
+    ))
+}
+
+/// Contents APIs return the file base64 encoded, sometimes split in lines.
+pub(crate) fn decode_content(content: &Value) -> Result<String, String> {
+    let b64: String = content
+        .as_str()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    match base64::engine::general_purpose::STANDARD.decode(b64) {
+        Ok(bytes) => String::from_utf8(bytes).map_err(|e| e.to_string()),
+        Err(e) => Err(format!("invalid base64 content: {}", e)),
+    }
+}
+
+fn encode_content(feed: &str) -> String {
+    base64::engine::general_purpose::STANDARD.encode(feed)
+}
+
+pub struct GitHub<'a> {
+    pub config: &'a GitHubConfig,
+    pub token: String,
+}
+
+impl Publisher for GitHub<'_> {
+    async fn fetch(&self) -> Result<FeedFile, String> {
+        match github_get(&self.config.contents_url, self.token.trim()).await? {
+            Some((content, sha)) => Ok(FeedFile {
+                content,
+                revision: sha.clone(),
+                sha,
+            }),
+            None => Ok(FeedFile::new_file()),
+        }
+    }
+
+    async fn publish(
+        &self,
+        feed: &str,
+        previous: &FeedFile,
+        message: &str,
+    ) -> Result<(), PublishError> {
+        github_commit(
+            &self.config.contents_url,
+            self.token.trim(),
+            &encode_content(feed),
+            &previous.revision,
+            message,
+        )
+        .await
+    }
+}
+
+pub struct Gitea<'a> {
+    pub config: &'a GiteaConfig,
+    pub token: String,
+}
+
+impl Gitea<'_> {
+    fn request(&self, method: Method) -> Result<RequestBuilder, String> {
+        let mut segments = vec!["repos", &self.config.owner, &self.config.repo, "contents"];
+        segments.extend(self.config.file.split('/'));
+        let url = api_url(&self.config.api_url, &segments)?;
+        Ok(Client::new().request(method, url).header(
+            header::AUTHORIZATION,
+            format!("token {}", self.token.trim()),
+        ))
+    }
+}
+
+impl Publisher for Gitea<'_> {
+    async fn fetch(&self) -> Result<FeedFile, String> {
+        let request = self
+            .request(Method::GET)?
+            .query(&[("ref", &self.config.branch)]);
+        match send(request).await? {
+            (200, file) => {
+                let sha = file["sha"].as_str().unwrap_or_default().to_string();
+                Ok(FeedFile {
+                    content: decode_content(&file["content"])?,
+                    revision: sha.clone(),
+                    sha,
+                })
+            }
+            //Not created yet
+            (404, _) => Ok(FeedFile::new_file()),
+            (status, body) => Err(format!(
+                "Gitea Server Error: {}, Response Body: {}",
+                status, body
+            )),
+        }
+    }
+
+    async fn publish(
+        &self,
+        feed: &str,
+        previous: &FeedFile,
+        message: &str,
+    ) -> Result<(), PublishError> {
+        let mut body = json!({
+            "branch": self.config.branch,
+            "content": encode_content(feed),
+            "message": message,
+            "author": {
+                "name": self.config.author_name,
+                "email": self.config.author_email
+            }
+        });
+        //POST creates the file, PUT updates it
+        let method = match previous.revision.is_empty() {
+            true => Method::POST,
+            false => {
+                body["sha"] = Value::from(previous.revision.as_str());
+                Method::PUT
+            }
+        };
+        match send(self.request(method)?.json(&body)).await? {
+            (200 | 201, _) => Ok(()),
+            (status @ (409 | 422), body) => Err(PublishError::Conflict(format!(
+                "Gitea Server Error: {}, Response Body: {}",
+                status, body
+            ))),
+            (status, body) => Err(PublishError::Server(format!(
+                "Gitea Server Error: {}, Response Body: {}",
+                status, body
+            ))),
+        }
+    }
+}
+
+pub struct GitLab<'a> {
+    pub config: &'a GitLabConfig,
+    pub token: String,
+}
+
+impl GitLab<'_> {
+    fn request(&self, method: Method) -> Result<RequestBuilder, String> {
+        //Both the project path and the file path go URL-encoded in a single segment
+        let segments = [
+            "projects",
+            &self.config.project,
+            "repository",
+            "files",
+            &self.config.file,
+        ];
+        let url = api_url(&self.config.api_url, &segments)?;
+        Ok(Client::new()
+            .request(method, url)
+            .header("PRIVATE-TOKEN", self.token.trim()))
+    }
+}
+
+impl Publisher for GitLab<'_> {
+    async fn fetch(&self) -> Result<FeedFile, String> {
+        let request = self
+            .request(Method::GET)?
+            .query(&[("ref", &self.config.branch)]);
+        match send(request).await? {
+            (200, file) => {
+                let content = decode_content(&file["content"])?;
+                Ok(FeedFile {
+                    sha: file["blob_id"]
+                        .as_str()
+                        .map_or_else(|| calc_git_sha1(content.as_bytes()), String::from),
+                    revision: file["last_commit_id"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    content,
+                })
+            }
+            //Not created yet
+            (404, _) => Ok(FeedFile::new_file()),
+            (status, body) => Err(format!(
+                "GitLab Server Error: {}, Response Body: {}",
+                status, body
+            )),
+        }
+    }
+
+    async fn publish(
+        &self,
+        feed: &str,
+        previous: &FeedFile,
+        message: &str,
+    ) -> Result<(), PublishError> {
+        let mut body = json!({
+            "branch": self.config.branch,
+            "content": encode_content(feed),
+            "encoding": "base64",
+            "commit_message": message,
+            "author_name": self.config.author_name,
+            "author_email": self.config.author_email
+        });
+        //POST creates the file, PUT updates it
+        let method = match previous.revision.is_empty() {
+            true => Method::POST,
+            false => {
+                body["last_commit_id"] = Value::from(previous.revision.as_str());
+                Method::PUT
+            }
+        };
+        match send(self.request(method)?.json(&body)).await? {
+            (200 | 201, _) => Ok(()),
+            //A stale last_commit_id is reported as a bad request
+            (400, body) if body.to_string().contains("has changed since") => {
+                Err(PublishError::Conflict(format!(
+                    "GitLab Server Error: 400, Response Body: {}",
+                    body
+                )))
+            }
+            (status, body) => Err(PublishError::Server(format!(
+                "GitLab Server Error: {}, Response Body: {}",
+                status, body
+            ))),
+        }
+    }
+}
+
+pub struct LocalGit<'a> {
+    pub config: &'a GitConfig,
+}
+
+impl Publisher for LocalGit<'_> {
+    async fn fetch(&self) -> Result<FeedFile, String> {
+        let (content, sha) = git::read_feed(self.config).await?;
+        Ok(FeedFile {
+            content,
+            revision: sha.clone(),
+            sha,
+        })
+    }
+
+    async fn publish(&self, feed: &str, _: &FeedFile, message: &str) -> Result<(), PublishError> {
+        git::commit(self.config, feed, message).await
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    fs::{self, File, Metadata},
+    io::Read,
+    os::unix::fs::MetadataExt,
+    path::Path,
+};
+
+const S_ISVTX: u32 = 0o1000;
+
+/// Effective ids of the running process, the ones the kernel checks file access against.
+struct User {
+    uid: u32,
+    gids: Vec<u32>,
+}
+
+impl User {
+    fn current() -> User {
+        //SAFETY: these calls have no preconditions, getgroups writes at most `len` ids
+        let (uid, mut gids) = unsafe { (libc::geteuid(), vec![libc::getegid()]) };
+        let len = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+        if len > 0 {
+            let mut groups = vec![0; len as usize];
+            let len = unsafe { libc::getgroups(len, groups.as_mut_ptr()) };
+            groups.truncate(len.max(0) as usize);
+            gids.extend(groups);
+        }
+        User { uid, gids }
+    }
+
+    /// Only root and the running user can replace what they own.
+    fn trusts(&self, meta: &Metadata) -> bool {
+        meta.uid() == 0 || meta.uid() == self.uid
+    }
+}
+
+fn check_file(path: &Path, meta: &Metadata, user: &User) -> Vec<String> {
+    let mut violations = Vec::new();
+    let mode = meta.mode() & 0o7777;
+    if !meta.file_type().is_file() {
+        violations.push(format!("{} is not a regular file", path.display()));
+        return violations;
+    }
+    if !user.trusts(meta) {
+        violations.push(format!(
+            "{} is owned by uid {}, not by the running user (uid {}) or root",
+            path.display(),
+            meta.uid(),
+            user.uid
+        ));
+    }
+    if mode & 0o070 != 0 && !user.gids.contains(&meta.gid()) {
+        violations.push(format!(
+            "{} is accessible by group {}, which the running user is not in",
+            path.display(),
+            meta.gid()
+        ));
+    }
+    if mode & 0o020 != 0 {
+        violations.push(format!(
+            "{} is writable by its group (mode {:04o})",
+            path.display(),
+            mode
+        ));
+    }
+    if mode & 0o007 != 0 {
+        violations.push(format!(
+            "{} is accessible by other users (mode {:04o})",
+            path.display(),
+            mode
+        ));
+    }
+    violations
+}
+
+/// Whoever can write to a directory can replace the files in it.
+fn check_dir(path: &Path, meta: &Metadata, user: &User) -> Vec<String> {
+    let mut violations = Vec::new();
+    if !user.trusts(meta) {
+        violations.push(format!(
+            "directory {} is owned by uid {}, not by the running user (uid {}) or root",
+            path.display(),
+            meta.uid(),
+            user.uid
+        ));
+    }
+    if meta.mode() & 0o002 != 0 && meta.mode() & S_ISVTX == 0 {
+        violations.push(format!(
+            "directory {} is writable by other users (mode {:04o})",
+            path.display(),
+            meta.mode() & 0o7777
+        ));
+    }
+    violations
+}
+
+/// Reads a file holding a secret, only if nobody but the running user, its group and root can
+/// read or replace it. A symbolic link is followed when it is owned by one of them, and the
+/// checks apply to its target. Every violation found is returned.
+pub fn read(path: &str) -> Result<String, Vec<String>> {
+    let user = User::current();
+    let link = fs::symlink_metadata(path).map_err(|e| vec![format!("{}: {}", path, e)])?;
+    let mut violations = Vec::new();
+    if link.file_type().is_symlink() && !user.trusts(&link) {
+        violations.push(format!(
+            "symbolic link {} is owned by uid {}, not by the running user (uid {}) or root",
+            path,
+            link.uid(),
+            user.uid
+        ));
+    }
+    let real = fs::canonicalize(path).map_err(|e| vec![format!("{}: {}", path, e)])?;
+    //Opening a FIFO or a device could block or have side effects
+    let meta = fs::metadata(&real).map_err(|e| vec![format!("{}: {}", real.display(), e)])?;
+    if !meta.file_type().is_file() {
+        violations.push(format!("{} is not a regular file", real.display()));
+        return Err(violations);
+    }
+    let mut file = File::open(&real).map_err(|e| vec![format!("{}: {}", real.display(), e)])?;
+    //Checked through the open file so it can't be swapped in between
+    let meta = file
+        .metadata()
+        .map_err(|e| vec![format!("{}: {}", real.display(), e)])?;
+    violations.extend(check_file(&real, &meta, &user));
+    for dir in real.ancestors().skip(1) {
+        match fs::metadata(dir) {
+            Ok(meta) => violations.extend(check_dir(dir, &meta, &user)),
+            Err(e) => violations.push(format!("{}: {}", dir.display(), e)),
+        }
+    }
+    if !violations.is_empty() {
+        return Err(violations);
+    }
+
+    let mut secret = String::new();
+    match file.read_to_string(&mut secret) {
+        Ok(_) => Ok(secret),
+        Err(e) => Err(vec![format!("{}: {}", real.display(), e)]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read;
+    use std::{
+        fs::{self, Permissions},
+        os::unix::fs::{symlink, PermissionsExt},
+        path::Path,
+    };
+    use tempfile::TempDir;
+
+    fn secret_dir() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::set_permissions(dir.path(), Permissions::from_mode(0o700)).unwrap();
+        dir
+    }
+
+    fn secret(dir: &Path, name: &str, mode: u32) -> String {
+        let path = dir.join(name);
+        fs::write(&path, "s3cr3t\n").unwrap();
+        fs::set_permissions(&path, Permissions::from_mode(mode)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn violations(path: &str) -> String {
+        read(path).unwrap_err().join("\n")
+    }
+
+    #[test]
+    fn reads_owner_only_file() {
+        let dir = secret_dir();
+        assert_eq!(
+            read(&secret(dir.path(), "token", 0o600)).unwrap(),
+            "s3cr3t\n"
+        );
+        assert!(read(&secret(dir.path(), "readonly", 0o400)).is_ok());
+    }
+
+    #[test]
+    fn reads_group_readable_file() {
+        let dir = secret_dir();
+        assert!(read(&secret(dir.path(), "token", 0o640)).is_ok());
+    }
+
+    #[test]
+    fn rejects_world_access() {
+        let dir = secret_dir();
+        for mode in [0o644, 0o602, 0o601] {
+            let path = secret(dir.path(), &format!("token{:o}", mode), mode);
+            assert!(violations(&path).contains("accessible by other users"));
+        }
+    }
+
+    #[test]
+    fn rejects_group_write() {
+        let dir = secret_dir();
+        assert!(violations(&secret(dir.path(), "token", 0o620)).contains("writable by its group"));
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let dir = secret_dir();
+        assert_eq!(
+            read(&secret(dir.path(), "token", 0o666)).unwrap_err().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn rejects_directory() {
+        let dir = secret_dir();
+        let path = dir.path().to_string_lossy().into_owned();
+        assert!(violations(&path).contains("is not a regular file"));
+    }
+
+    #[test]
+    fn rejects_missing_file() {
+        let dir = secret_dir();
+        let path = dir.path().join("missing");
+        assert!(read(&path.to_string_lossy()).is_err());
+    }
+
+    #[test]
+    fn follows_symlink_to_secure_file() {
+        let dir = secret_dir();
+        let target = secret(dir.path(), "token", 0o600);
+        let link = dir.path().join("link");
+        symlink(&target, &link).unwrap();
+        assert_eq!(read(&link.to_string_lossy()).unwrap(), "s3cr3t\n");
+    }
+
+    #[test]
+    fn checks_symlink_target() {
+        let dir = secret_dir();
+        let target = secret(dir.path(), "token", 0o644);
+        let link = dir.path().join("link");
+        symlink(&target, &link).unwrap();
+        assert!(violations(&link.to_string_lossy()).contains("accessible by other users"));
+    }
+
+    #[test]
+    fn rejects_world_writable_parent() {
+        let dir = secret_dir();
+        let shared = dir.path().join("shared");
+        fs::create_dir(&shared).unwrap();
+        let path = secret(&shared, "token", 0o600);
+        fs::set_permissions(&shared, Permissions::from_mode(0o777)).unwrap();
+        assert!(violations(&path).contains("writable by other users"));
+    }
+
+    #[test]
+    fn allows_sticky_parent() {
+        let dir = secret_dir();
+        let shared = dir.path().join("shared");
+        fs::create_dir(&shared).unwrap();
+        let path = secret(&shared, "token", 0o600);
+        fs::set_permissions(&shared, Permissions::from_mode(0o1777)).unwrap();
+        assert!(read(&path).is_ok());
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{
+    check_valid, collect_entries,
+    config::Config,
+    daemon, get_feed_updated,
+    html::calc_git_sha1,
+    load_templates, render_feed, templates,
+    websub::{self, Hub},
+    RunError, ATOM_CONTENT_TYPE, ISO_UTC_FORMAT,
+};
+use chrono::{DateTime, Utc};
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use std::{
+    collections::HashMap, convert::Infallible, net::SocketAddr, path::Path, process::exit,
+    sync::Arc,
+};
+use tokio::sync::{oneshot, RwLock};
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// A rendered file with the validators of its current version.
+struct Document {
+    body: String,
+    content_type: &'static str,
+    etag: String,
+    last_modified: DateTime<Utc>,
+}
+
+impl Document {
+    fn new(body: String, content_type: &'static str) -> Document {
+        Document {
+            etag: format!("\"{}\"", calc_git_sha1(body.as_bytes())),
+            body,
+            content_type,
+            last_modified: Utc::now(),
+        }
+    }
+}
+
+/// Documents by request path.
+type Documents = Arc<RwLock<HashMap<String, Arc<Document>>>>;
+
+fn content_type(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("atom") => ATOM_CONTENT_TYPE,
+        Some("rss") => "application/rss+xml; charset=utf-8",
+        Some("xml") => "application/xml; charset=utf-8",
+        Some("json") => "application/json",
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+/// Renders the feed and the extra outputs. A document whose body doesn't change keeps its
+/// validators, so clients keep getting 304. Returns the feed when it changed.
+async fn regenerate(config: &Config, documents: &Documents) -> Result<Option<String>, RunError> {
+    let current = documents.read().await.clone();
+    let previous = current.get(&config.serve.feed_path);
+    let old_feed = previous.map_or("", |d| d.body.as_str());
+    let (entries, _) = collect_entries(config, old_feed).await?;
+    let tera = load_templates(config)?;
+
+    let mut rendered = HashMap::new();
+    let sha = calc_git_sha1(old_feed.as_bytes());
+    let (feed, changed) = match (
+        render_feed(&tera, &config.feed, old_feed, &entries, &sha)?,
+        previous,
+    ) {
+        (Some(feed), _) => {
+            check_valid(&feed)?;
+            (Arc::new(Document::new(feed, ATOM_CONTENT_TYPE)), true)
+        }
+        (None, Some(document)) => (document.clone(), false),
+        //Nothing is stored, so requests keep getting 503 as before the first render
+        (None, None) => {
+            return Err(RunError::from(String::from(
+                "Error rendering feed: unchanged but never rendered",
+            )))
+        }
+    };
+    //Outputs take the date of the feed so they only change along with it
+    let updated = get_feed_updated(&feed.body).map_or_else(
+        || Utc::now().format(ISO_UTC_FORMAT).to_string(),
+        String::from,
+    );
+    let context = templates::feed_context(&config.feed, &updated, &entries);
+    for output in &config.outputs {
+        let name = Path::new(&output.path)
+            .file_name()
+            .map_or(output.path.clone(), |n| n.to_string_lossy().into_owned());
+        let path = format!("/{}", name);
+        match templates::render(&tera, &output.template, &context) {
+            Ok(body) => match current.get(&path) {
+                Some(document) if document.body == body => {
+                    rendered.insert(path, document.clone());
+                }
+                _ => {
+                    rendered.insert(path, Arc::new(Document::new(body, content_type(&name))));
+                }
+            },
+            Err(e) => {
+                eprintln!("Error rendering {}: {}", output.template, e);
+                if let Some(document) = current.get(&path) {
+                    rendered.insert(path, document.clone());
+                }
+            }
+        }
+    }
+    let changed = changed.then(|| feed.body.clone());
+    rendered.insert(config.serve.feed_path.clone(), feed);
+    *documents.write().await = rendered;
+    Ok(changed)
+}
+
+/// `If-None-Match` wins over `If-Modified-Since`, like RFC 9110 asks.
+fn not_modified(req: &Request<Body>, document: &Document) -> bool {
+    let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+    match header(header::IF_NONE_MATCH) {
+        Some(tags) => tags
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == document.etag),
+        None => header(header::IF_MODIFIED_SINCE)
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .is_some_and(|since| document.last_modified.timestamp() <= since.timestamp()),
+    }
+}
+
+async fn handle(
+    req: Request<Body>,
+    documents: Documents,
+    hub: Option<(String, Arc<Hub>)>,
+) -> Result<Response<Body>, Infallible> {
+    if let Some((_, hub)) = hub.filter(|(path, _)| path == req.uri().path()) {
+        return Ok(match req.method() {
+            &Method::POST => hub.handle(req).await,
+            _ => websub::method_not_allowed(),
+        });
+    }
+    let response = Response::builder();
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return Ok(response
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(header::ALLOW, "GET, HEAD")
+            .body(Body::empty())
+            .unwrap_or_default());
+    }
+    let (document, ready) = {
+        let documents = documents.read().await;
+        (
+            documents.get(req.uri().path()).cloned(),
+            !documents.is_empty(),
+        )
+    };
+    let document = match document {
+        Some(document) => document,
+        //Nothing rendered yet
+        None if !ready => {
+            return Ok(response
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(header::RETRY_AFTER, "60")
+                .body(Body::empty())
+                .unwrap_or_default())
+        }
+        None => {
+            return Ok(response
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap_or_default())
+        }
+    };
+
+    let response = response.header(header::ETAG, &document.etag).header(
+        header::LAST_MODIFIED,
+        document.last_modified.format(HTTP_DATE_FORMAT).to_string(),
+    );
+    if not_modified(&req, &document) {
+        return Ok(response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap_or_default());
+    }
+    let response = response
+        .header(header::CONTENT_TYPE, document.content_type)
+        .header(header::CONTENT_LENGTH, document.body.len());
+    let body = match req.method() {
+        &Method::HEAD => Body::empty(),
+        _ => Body::from(document.body.clone()),
+    };
+    Ok(response.body(body).unwrap_or_default())
+}
+
+/// Serves the feed from memory while regenerating it on the `daemon` schedule. A run that
+/// fails leaves the documents of the previous one.
+pub async fn run(config: Config) {
+    let addr: SocketAddr = match config.serve.listen.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            eprintln!("Config Error: serve.listen {}: {}", config.serve.listen, e);
+            exit(1);
+        }
+    };
+    let documents = Documents::default();
+    //Subscribers find the hub through the feed, so it is announced as feed.hub
+    let hub = config.serve.hub_path.as_ref().map(|path| {
+        let url = match config.feed.hub {
+            Some(ref url) => url.clone(),
+            None => {
+                eprintln!("Warning: serve.hub_path is set but feed.hub doesn't announce it");
+                path.clone()
+            }
+        };
+        (path.clone(), Arc::new(Hub::new(&url, &config.feed.link)))
+    });
+    let server = match Server::try_bind(&addr) {
+        Ok(builder) => {
+            let (documents, hub) = (documents.clone(), hub.clone());
+            builder.serve(make_service_fn(move |_| {
+                let (documents, hub) = (documents.clone(), hub.clone());
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req| {
+                        handle(req, documents.clone(), hub.clone())
+                    }))
+                }
+            }))
+        }
+        Err(e) => {
+            eprintln!("Cannot listen on {}: {}", addr, e);
+            exit(1);
+        }
+    };
+    println!("Serving {} on http://{}", config.serve.feed_path, addr);
+    let (stop, stopped) = oneshot::channel::<()>();
+    let server = tokio::spawn(server.with_graceful_shutdown(async {
+        stopped.await.ok();
+    }));
+
+    daemon::schedule(config, |config| {
+        let (documents, hub) = (documents.clone(), hub.clone());
+        async move {
+            if let Some(feed) = regenerate(&config, &documents).await? {
+                match hub {
+                    Some((_, hub)) => hub.distribute(&feed).await,
+                    None => websub::notify(&config.feed).await,
+                }
+            }
+            Ok(())
+        }
+    })
+    .await;
+
+    stop.send(()).ok();
+    if let Ok(Err(e)) = server.await {
+        eprintln!("Server Error: {}", e);
+    }
+}
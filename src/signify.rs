@@ -0,0 +1,214 @@
+use crate::crypto::hex;
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use std::{fmt, fs};
+
+const PKALG: &[u8] = b"Ed";
+const COMMENT_HEADER: &str = "untrusted comment: ";
+
+#[derive(Debug)]
+pub struct PublicKey {
+    pub name: String,
+    keynum: [u8; 8],
+    key: VerifyingKey,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    Verified(String),
+    Unsigned,
+    /// Signed with a key not in `keys_dir`, like the one of a release not installed yet.
+    UnknownKey(String),
+    BadSignature(String),
+    /// The `.patch.sig` file couldn't be downloaded, so nothing was checked.
+    Unavailable(String),
+}
+
+impl fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureStatus::Verified(key) => write!(f, "verified with {}", key),
+            SignatureStatus::Unsigned => write!(f, "unsigned"),
+            SignatureStatus::UnknownKey(keynum) => write!(f, "signed with unknown key {}", keynum),
+            SignatureStatus::BadSignature(reason) => write!(f, "BAD SIGNATURE ({})", reason),
+            SignatureStatus::Unavailable(reason) => write!(f, "not checked ({})", reason),
+        }
+    }
+}
+
+/// Splits a signify file into its decoded base64 line and whatever follows it.
+fn decode_signify_block(bytes: &[u8]) -> Result<(Vec<u8>, &[u8]), String> {
+    let mut lines = bytes.splitn(3, |b| *b == b'\n');
+    match lines.next() {
+        Some(comment) if comment.starts_with(COMMENT_HEADER.as_bytes()) => (),
+        _ => return Err(String::from("missing untrusted comment")),
+    }
+    let b64 = match lines.next() {
+        Some(b64) => b64,
+        None => return Err(String::from("missing base64 line")),
+    };
+    match base64::engine::general_purpose::STANDARD.decode(b64) {
+        Ok(data) if data.starts_with(PKALG) => Ok((data, lines.next().unwrap_or_default())),
+        Ok(_) => Err(String::from("unsupported signature algorithm")),
+        Err(e) => Err(format!("invalid base64: {}", e)),
+    }
+}
+
+impl PublicKey {
+    pub fn parse(name: &str, bytes: &[u8]) -> Result<PublicKey, String> {
+        let (data, _) = decode_signify_block(bytes)?;
+        let raw: [u8; 42] = match data.as_slice().try_into() {
+            Ok(raw) => raw,
+            Err(_) => return Err(format!("wrong public key length: {}", data.len())),
+        };
+        let mut keynum = [0u8; 8];
+        keynum.copy_from_slice(&raw[2..10]);
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&raw[10..]);
+        match VerifyingKey::from_bytes(&key) {
+            Ok(key) => Ok(PublicKey {
+                name: name.to_string(),
+                keynum,
+                key,
+            }),
+            Err(e) => Err(format!("invalid Ed25519 key: {}", e)),
+        }
+    }
+}
+
+/// Loads every `openbsd-XX-base.pub` file in `dir`. Unreadable keys are reported and skipped,
+/// but finding none at all is an error: every patch would fail verification.
+pub fn load_keys(dir: &str) -> Result<Vec<PublicKey>, String> {
+    let dir_entries = fs::read_dir(dir)
+        .map_err(|e| format!("Error reading signify keys directory {}: {}", dir, e))?;
+    let mut keys = Vec::<PublicKey>::new();
+    for dir_entry in dir_entries.flatten() {
+        let name = dir_entry.file_name().to_string_lossy().to_string();
+        if !(name.starts_with("openbsd-") && name.ends_with("-base.pub")) {
+            continue;
+        }
+        match fs::read(dir_entry.path()).map_err(|e| e.to_string()) {
+            Ok(bytes) => match PublicKey::parse(&name, &bytes) {
+                Ok(key) => keys.push(key),
+                Err(e) => eprintln!("Signify Error: {}: {}", name, e),
+            },
+            Err(e) => eprintln!("Error reading signify key {}: {}", name, e),
+        }
+    }
+    match keys.is_empty() {
+        true => Err(format!(
+            "Config Error: no usable openbsd-XX-base.pub key in {}",
+            dir
+        )),
+        false => Ok(keys),
+    }
+}
+
+/// Whether `sig_file` looks like a `signify -Se` file, whatever its signature is worth. Pages
+/// like the ones of a captive portal don't.
+pub fn is_embedded_signature(sig_file: &[u8]) -> bool {
+    decode_signify_block(sig_file).is_ok_and(|(data, _)| data.len() == 74)
+}
+
+/// Verifies a `signify -Se` embedded signature, like the `.patch.sig` files on the mirrors.
+pub fn verify_embedded(sig_file: &[u8], keys: &[PublicKey]) -> SignatureStatus {
+    let (data, message) = match decode_signify_block(sig_file) {
+        Ok(block) => block,
+        Err(e) => return SignatureStatus::BadSignature(e),
+    };
+    let raw: [u8; 74] = match data.as_slice().try_into() {
+        Ok(raw) => raw,
+        Err(_) => {
+            return SignatureStatus::BadSignature(format!("wrong signature length: {}", data.len()))
+        }
+    };
+    let key = match keys.iter().find(|key| key.keynum == raw[2..10]) {
+        Some(key) => key,
+        None => return SignatureStatus::UnknownKey(hex(&raw[2..10])),
+    };
+    let signature = Signature::from_slice(&raw[10..]).map_err(|e| e.to_string());
+    match signature.and_then(|sig| {
+        key.key
+            .verify_strict(message, &sig)
+            .map_err(|e| e.to_string())
+    }) {
+        Ok(_) => SignatureStatus::Verified(key.name.clone()),
+        Err(e) => SignatureStatus::BadSignature(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_embedded_signature, verify_embedded, PublicKey, SignatureStatus};
+
+    //Written in the signify format from a fixed Ed25519 seed, keynum 0123456789abcdef
+    const PUBLIC_KEY: &str = "untrusted comment: openbsd 7.4 base public key
+RWQBI0VniavN7wOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4
+";
+    //The same key under keynum fedcba9876543210
+    const OTHER_KEY: &str = "untrusted comment: openbsd 7.5 base public key
+RWT+3LqYdlQyEAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4
+";
+    const SIGNED_PATCH: &str = "untrusted comment: verify with openbsd-74-base.pub
+RWQBI0VniavN70DZNFf03zBpHleocFvcsHHQiHFJMUkqBQTx3JKwBoP8AMvxJMw4a7ZPZMRixnrR+I8EyVoJ7k5Jtp+A//pXVA0=
+Index: usr.sbin/smtpd/smtp.c
+--- usr.sbin/smtpd/smtp.c.orig
++++ usr.sbin/smtpd/smtp.c
+@@ -1 +1 @@
+-old
++new
+";
+
+    fn key(name: &str, file: &str) -> PublicKey {
+        PublicKey::parse(name, file.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn verifies_signed_patch() {
+        let keys = [
+            key("openbsd-75-base.pub", OTHER_KEY),
+            key("openbsd-74-base.pub", PUBLIC_KEY),
+        ];
+        assert!(is_embedded_signature(SIGNED_PATCH.as_bytes()));
+        assert_eq!(
+            verify_embedded(SIGNED_PATCH.as_bytes(), &keys),
+            SignatureStatus::Verified(String::from("openbsd-74-base.pub"))
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_patch() {
+        let tampered = SIGNED_PATCH.replace("+new", "+evil");
+        let keys = [key("openbsd-74-base.pub", PUBLIC_KEY)];
+        assert!(matches!(
+            verify_embedded(tampered.as_bytes(), &keys),
+            SignatureStatus::BadSignature(_)
+        ));
+    }
+
+    #[test]
+    fn reports_unknown_key() {
+        let keys = [key("openbsd-75-base.pub", OTHER_KEY)];
+        assert_eq!(
+            verify_embedded(SIGNED_PATCH.as_bytes(), &keys),
+            SignatureStatus::UnknownKey(String::from("0123456789abcdef"))
+        );
+    }
+
+    #[test]
+    fn rejects_missing_lines() {
+        let (_, without_comment) = SIGNED_PATCH.split_once('\n').unwrap();
+        assert!(PublicKey::parse("openbsd-74-base.pub", b"RWQBI0VniavN7w==\n").is_err());
+        assert!(PublicKey::parse("openbsd-74-base.pub", b"untrusted comment: key").is_err());
+        assert!(!is_embedded_signature(without_comment.as_bytes()));
+        assert_eq!(
+            verify_embedded(without_comment.as_bytes(), &[key("k", PUBLIC_KEY)]),
+            SignatureStatus::BadSignature(String::from("missing untrusted comment"))
+        );
+        assert_eq!(
+            verify_embedded(b"untrusted comment: verify with openbsd-74-base.pub", &[]),
+            SignatureStatus::BadSignature(String::from("missing base64 line"))
+        );
+        assert!(!is_embedded_signature(b"<html>captive portal</html>"));
+    }
+}
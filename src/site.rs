@@ -0,0 +1,78 @@
+use crate::{
+    atom_entry::AtomEntry,
+    config::FeedConfig,
+    release_name,
+    templates::{self, entry_context},
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::{fs, path::Path};
+use tera::Tera;
+
+fn write_page(tera: &Tera, template: &str, context: &Value, path: &Path) -> Result<(), String> {
+    let page = templates::render(tera, template, context)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    fs::write(path, page).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Writes an index of releases, one page per release and one page per erratum into `dir`.
+pub fn generate(
+    tera: &Tera,
+    feed: &FeedConfig,
+    entries: &[AtomEntry],
+    dir: &str,
+) -> Result<(), String> {
+    let dir = Path::new(dir);
+    let mut versions: Vec<u16> = entries.iter().map(|e| e.release_version).collect();
+    versions.sort_unstable_by(|a, b| b.cmp(a));
+    versions.dedup();
+
+    let releases: Vec<Value> = versions
+        .iter()
+        .map(|version| {
+            let mut release_entries: Vec<&AtomEntry> = entries
+                .iter()
+                .filter(|e| e.release_version == *version)
+                .collect();
+            release_entries.sort_by_key(|e| e.iteration_count);
+            json!({
+                "name": release_name(*version),
+                "version": version,
+                "entries": release_entries.into_iter().map(entry_context).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    let site = json!({
+        "feed_title": feed.title,
+        "feed_link": feed.link,
+        "generated": Utc::now().format("%Y-%m-%d %H:%M UTC").to_string(),
+        "releases": releases,
+    });
+    write_page(tera, "site/index.html", &site, &dir.join("index.html"))?;
+
+    for release in &releases {
+        let release_dir = dir.join(release["name"].as_str().unwrap_or_default());
+        let mut context = site.clone();
+        context["release"] = release.clone();
+        write_page(
+            tera,
+            "site/release.html",
+            &context,
+            &release_dir.join("index.html"),
+        )?;
+
+        for entry in release["entries"].as_array().into_iter().flatten() {
+            context["entry"] = entry.clone();
+            let page = format!("{}.html", entry["page"].as_str().unwrap_or_default());
+            write_page(tera, "site/patch.html", &context, &release_dir.join(page))?;
+        }
+    }
+    println!(
+        "Generated static site for {} errata in {}",
+        entries.len(),
+        dir.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use crate::{atom_entry::AtomEntry, config::FeedConfig, release_name, ISO_UTC_FORMAT};
+use serde_json::{json, Value};
+use std::{collections::HashMap, error::Error, fs, path::Path};
+use tera::{Context, Tera};
+
+pub const FEED_TEMPLATE: &str = "feed/atom.xml";
+
+pub const EMAIL_TEXT_TEMPLATE: &str = "email/message.txt";
+pub const EMAIL_HTML_TEMPLATE: &str = "email/message.html";
+pub const WEBHOOK_TEMPLATE: &str = "webhook/message.json";
+
+const DEFAULT_TEMPLATES: [(&str, &str); 8] = [
+    (FEED_TEMPLATE, include_str!("../templates/feed/atom.xml")),
+    (
+        "feed/entry.xml",
+        include_str!("../templates/feed/entry.xml"),
+    ),
+    (
+        "site/index.html",
+        include_str!("../templates/site/index.html"),
+    ),
+    (
+        "site/release.html",
+        include_str!("../templates/site/release.html"),
+    ),
+    (
+        "site/patch.html",
+        include_str!("../templates/site/patch.html"),
+    ),
+    (
+        EMAIL_TEXT_TEMPLATE,
+        include_str!("../templates/email/message.txt"),
+    ),
+    (
+        EMAIL_HTML_TEMPLATE,
+        include_str!("../templates/email/message.html"),
+    ),
+    (
+        WEBHOOK_TEMPLATE,
+        include_str!("../templates/webhook/message.json"),
+    ),
+];
+
+fn describe(e: &tera::Error) -> String {
+    let mut description = e.to_string();
+    let mut source = e.source();
+    while let Some(cause) = source {
+        description += &format!(": {}", cause);
+        source = cause.source();
+    }
+    description
+}
+
+fn string_filter(
+    encode: fn(&str) -> String,
+) -> impl Fn(&Value, &HashMap<String, Value>) -> tera::Result<Value> {
+    move |value, _| {
+        Ok(Value::String(match value {
+            Value::String(s) => encode(s),
+            v => encode(&v.to_string()),
+        }))
+    }
+}
+
+fn read_template(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map_err(|e| format!("Error reading template {}: {}", path.display(), e))
+}
+
+/// Built-in templates, each one replaced by the file with the same relative path in `dir`,
+/// plus the `extra` templates that only exist in `dir`.
+///
+/// XML templates are not autoescaped, they escape with the `encode_safe` and `encode_attr`
+/// filters instead so the feed stays byte for byte what `html_escape` produces.
+pub fn load(dir: Option<&str>, extra: &[&str]) -> Result<Tera, String> {
+    let mut tera = Tera::default();
+    tera.autoescape_on(vec![".html", ".htm"]);
+    tera.register_filter(
+        "encode_safe",
+        string_filter(|s| html_escape::encode_safe(s).to_string()),
+    );
+    tera.register_filter(
+        "encode_attr",
+        string_filter(|s| html_escape::encode_double_quoted_attribute(s).to_string()),
+    );
+    tera.register_filter(
+        "decode_html",
+        string_filter(|s| html_escape::decode_html_entities(s).to_string()),
+    );
+
+    let mut templates = Vec::<(String, String)>::new();
+    for (name, default) in DEFAULT_TEMPLATES {
+        let template = match dir.map(|dir| Path::new(dir).join(name)) {
+            Some(path) if path.is_file() => read_template(&path)?,
+            _ => default.to_string(),
+        };
+        templates.push((name.to_string(), template));
+    }
+    for name in extra {
+        match dir {
+            Some(dir) => {
+                templates.push((name.to_string(), read_template(&Path::new(dir).join(name))?))
+            }
+            None => return Err(format!("Template Error: {} needs a templates_dir", name)),
+        }
+    }
+    tera.add_raw_templates(templates)
+        .map_err(|e| format!("Template Error: {}", describe(&e)))?;
+    Ok(tera)
+}
+
+pub fn render(tera: &Tera, name: &str, context: &Value) -> Result<String, String> {
+    let context = Context::from_value(context.clone()).map_err(|e| describe(&e))?;
+    tera.render(name, &context).map_err(|e| describe(&e))
+}
+
+/// File name used for an entry's own page, e.g. `001_x509` or `nopatch003`.
+pub fn page_name(entry: &AtomEntry) -> String {
+    let name = &entry.id[entry.id.find("-").map_or(0, |i| i + 1)..];
+    name.trim_start_matches('*').to_string()
+}
+
+/// Every `AtomEntry` field as seen from the templates.
+pub fn entry_context(entry: &AtomEntry) -> Value {
+    json!({
+        "id": entry.id,
+        "page": page_name(entry),
+        "kind": entry.kind,
+        "title": entry.title,
+        "updated": entry.updated.format(ISO_UTC_FORMAT).to_string(),
+        "date": entry.updated.format("%Y-%m-%d").to_string(),
+        "link": entry.link,
+        "content": entry.content,
+        "release": release_name(entry.release_version),
+        "patch_url": entry.patch_url,
+        "signature": entry.signature.as_ref().map(|s| s.to_string()),
+        "files": entry.files.iter().map(|f| json!({
+            "path": f.path,
+            "added": f.added,
+            "removed": f.removed,
+        })).collect::<Vec<_>>(),
+        "impact": entry.impact.as_ref().map(|i| json!({
+            "term": i.term(),
+            "label": i.label(),
+        })),
+        "cves": entry.cves,
+        "advisories": entry.advisories,
+        "cvss": entry.cvss.as_ref().map(|c| json!({
+            "cve": c.cve,
+            "score": c.score,
+            "severity": c.severity.to_string(),
+            "vector": c.vector,
+        })),
+    })
+}
+
+/// Feed metadata and entries, as seen from the feed and extra output templates.
+pub fn feed_context(feed: &FeedConfig, updated: &str, entries: &[AtomEntry]) -> Value {
+    json!({
+        "feed": {
+            "title": feed.title,
+            "link": feed.link,
+            "link_rel": feed.link_rel,
+            "updated": updated,
+            "author_name": feed.author_name,
+            "author_uri": feed.author_uri,
+            "id": feed.id,
+            "id_prefix": feed.id_prefix,
+            "hub": feed.hub,
+        },
+        "entries": entries.iter().map(entry_context).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{feed_context, load, render, FEED_TEMPLATE};
+    use crate::{
+        atom_entry::AtomEntry, config::FeedConfig, FEED_AUTHOR_NAME, FEED_AUTHOR_URI, FEED_LINK,
+        FEED_LINK_REL, FEED_TITLE, FEED_UUID, ISO_UTC_FORMAT,
+    };
+    use chrono::DateTime;
+
+    const UPDATED: &str = "2024-01-10T12:00:00Z";
+
+    fn entry() -> AtomEntry {
+        AtomEntry {
+            id: String::from("v74-p001_xserver"),
+            kind: String::from("security fix"),
+            title: String::from("001: SECURITY FIX: January 10, 2024 <All architectures> & more"),
+            updated: DateTime::parse_from_rfc3339("2024-01-10T00:00:00Z").unwrap(),
+            link: String::from("https://www.openbsd.org/errata74.html#p001_xserver"),
+            content: String::from("<b>Fix</b> it: \"quoted\" 'text' in a/b"),
+            release_version: 74,
+            iteration_count: 0,
+            patch_url: None,
+            signature: None,
+            files: Vec::new(),
+            impact: None,
+            cves: Vec::new(),
+            advisories: Vec::new(),
+            cvss: None,
+        }
+    }
+
+    //How the feed was written before it came from templates
+    fn baseline(entries: &[AtomEntry]) -> String {
+        let mut feed = format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+                "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
+                "    <title>{title}</title>\n",
+                "    <link rel=\"self\" href=\"{link}\"/>\n",
+                "    <link rel=\"related\" href=\"{link_rel}\"/>\n",
+                "    <updated>{updated}</updated>\n",
+                "    <author>\n",
+                "        <name>{author_name}</name>\n",
+                "        <uri>{author_uri}</uri>\n",
+                "    </author>\n",
+                "    <id>{id}</id>\n",
+            ),
+            title = FEED_TITLE,
+            link = FEED_LINK,
+            link_rel = FEED_LINK_REL,
+            updated = UPDATED,
+            author_name = FEED_AUTHOR_NAME,
+            author_uri = FEED_AUTHOR_URI,
+            id = FEED_UUID
+        );
+        for entry in entries {
+            feed += &format!(
+                concat!(
+                    "   <entry>\n",
+                    "       <id>{id_prefix}/{id}</id>\n",
+                    "       <title type=\"html\">{title}</title>\n",
+                    "       <updated>{updated}</updated>\n",
+                    "       <content type=\"html\">{content}</content>\n",
+                    "       <link rel=\"alternate\" type=\"text/html\" href=\"{link}\"/>\n",
+                    "   </entry>\n"
+                ),
+                id_prefix = "tag:albert.goma.cat,2023:syspatch_feed",
+                id = entry.id,
+                title = html_escape::encode_safe(&entry.title),
+                updated = entry.updated.format(ISO_UTC_FORMAT),
+                content = html_escape::encode_safe(&entry.content),
+                link = entry.link,
+            );
+        }
+        feed + "</feed>"
+    }
+
+    fn rendered(entries: &[AtomEntry]) -> String {
+        let tera = load(None, &[]).unwrap();
+        let context = feed_context(&FeedConfig::default(), UPDATED, entries);
+        render(&tera, FEED_TEMPLATE, &context).unwrap()
+    }
+
+    #[test]
+    fn default_feed_template_matches_baseline() {
+        let mut second = entry();
+        second.id = String::from("v73-p009_libc");
+        second.title = String::from("009: RELIABILITY FIX");
+        let entries = [entry(), second];
+        assert_eq!(rendered(&entries), baseline(&entries));
+    }
+
+    #[test]
+    fn default_feed_template_matches_baseline_without_entries() {
+        assert_eq!(rendered(&[]), baseline(&[]));
+    }
+}
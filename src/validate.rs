@@ -0,0 +1,246 @@
+use chrono::DateTime;
+use roxmltree::{Document, Node};
+use std::collections::HashSet;
+
+const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+
+pub(crate) fn atom_children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &str,
+) -> Vec<Node<'a, 'input>> {
+    node.children()
+        .filter(|c| c.is_element() && c.tag_name().namespace() == Some(ATOM_NS))
+        .filter(|c| c.tag_name().name() == name)
+        .collect()
+}
+
+struct Validator<'a, 'input> {
+    doc: &'a Document<'input>,
+    diagnostics: Vec<String>,
+}
+
+impl<'a, 'input> Validator<'a, 'input> {
+    fn report(&mut self, node: Node, message: String) {
+        let pos = self.doc.text_pos_at(node.range().start);
+        self.diagnostics
+            .push(format!("{}:{}: {}", pos.row, pos.col, message));
+    }
+
+    /// Returns the only `name` child of `node`, reporting it when missing or repeated.
+    fn exactly_one<'n, 'i>(&mut self, node: Node<'n, 'i>, name: &str) -> Option<Node<'n, 'i>> {
+        let children = atom_children(node, name);
+        match children.as_slice() {
+            [child] => Some(*child),
+            [] => {
+                self.report(
+                    node,
+                    format!("<{}> is missing <{}>", node.tag_name().name(), name),
+                );
+                None
+            }
+            [_, extra, ..] => {
+                self.report(
+                    *extra,
+                    format!("<{}> has more than one <{}>", node.tag_name().name(), name),
+                );
+                Some(children[0])
+            }
+        }
+    }
+
+    fn check_text_construct(&mut self, node: Node) {
+        match node.attribute("type") {
+            None | Some("text") | Some("html") | Some("xhtml") => (),
+            Some(t) => self.report(node, format!("invalid text construct type \"{}\"", t)),
+        }
+    }
+
+    fn check_date(&mut self, node: Node) {
+        let text = node.text().unwrap_or_default();
+        if let Err(e) = DateTime::parse_from_rfc3339(text) {
+            self.report(node, format!("\"{}\" is not an RFC 3339 date: {}", text, e));
+        }
+    }
+
+    fn check_id(&mut self, node: Node) -> Option<String> {
+        let id = node.text().unwrap_or_default().trim();
+        if id.is_empty() || !id.contains(':') {
+            self.report(node, format!("\"{}\" is not an absolute IRI", id));
+            return None;
+        }
+        Some(id.to_string())
+    }
+
+    fn check_links(&mut self, node: Node) {
+        let mut alternates = HashSet::<(String, String)>::new();
+        for link in atom_children(node, "link") {
+            if link.attribute("href").is_none() {
+                self.report(link, String::from("<link> without href"));
+            }
+            if link.attribute("rel").unwrap_or("alternate") == "alternate" {
+                let key = (
+                    link.attribute("type").unwrap_or_default().to_string(),
+                    link.attribute("hreflang").unwrap_or_default().to_string(),
+                );
+                if !alternates.insert(key) {
+                    self.report(
+                        link,
+                        String::from(
+                            "more than one alternate <link> with the same type and hreflang",
+                        ),
+                    );
+                }
+            }
+        }
+        for category in atom_children(node, "category") {
+            if category.attribute("term").is_none() {
+                self.report(category, String::from("<category> without term"));
+            }
+        }
+    }
+
+    fn check_common(&mut self, node: Node) -> Option<String> {
+        let id = self
+            .exactly_one(node, "id")
+            .and_then(|id| self.check_id(id));
+        if let Some(title) = self.exactly_one(node, "title") {
+            self.check_text_construct(title);
+        }
+        if let Some(updated) = self.exactly_one(node, "updated") {
+            self.check_date(updated);
+        }
+        self.check_links(node);
+        id
+    }
+
+    fn check_entry(&mut self, entry: Node, feed_has_author: bool) -> Option<String> {
+        let id = self.check_common(entry);
+        if !feed_has_author && atom_children(entry, "author").is_empty() {
+            self.report(
+                entry,
+                String::from("<entry> without <author> in a feed without one"),
+            );
+        }
+        match atom_children(entry, "content").as_slice() {
+            [] => {
+                let has_alternate = atom_children(entry, "link")
+                    .iter()
+                    .any(|l| l.attribute("rel").unwrap_or("alternate") == "alternate");
+                if !has_alternate {
+                    self.report(
+                        entry,
+                        String::from("<entry> without <content> nor alternate <link>"),
+                    );
+                }
+            }
+            [content] => self.check_text_construct(*content),
+            [_, extra, ..] => {
+                self.report(*extra, String::from("<entry> has more than one <content>"))
+            }
+        }
+        id
+    }
+}
+
+/// Checks the RFC 4287 requirements the feed relies on. Each diagnostic starts with `row:col`.
+pub fn validate_feed(xml: &str) -> Result<(), Vec<String>> {
+    let doc = match Document::parse(xml) {
+        Ok(doc) => doc,
+        Err(e) => return Err(vec![format!("XML is not well-formed: {}", e)]),
+    };
+    let mut validator = Validator {
+        doc: &doc,
+        diagnostics: Vec::new(),
+    };
+
+    let feed = doc.root_element();
+    if feed.tag_name().name() != "feed" || feed.tag_name().namespace() != Some(ATOM_NS) {
+        validator.report(feed, String::from("root element is not an Atom <feed>"));
+        return Err(validator.diagnostics);
+    }
+    validator.check_common(feed);
+    let feed_has_author = !atom_children(feed, "author").is_empty();
+
+    let mut ids = HashSet::<String>::new();
+    for entry in atom_children(feed, "entry") {
+        if let Some(id) = validator.check_entry(entry, feed_has_author) {
+            if !ids.insert(id.clone()) {
+                validator.report(entry, format!("duplicate entry id {}", id));
+            }
+        }
+    }
+
+    match validator.diagnostics.is_empty() {
+        true => Ok(()),
+        false => Err(validator.diagnostics),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_feed;
+
+    const ENTRY: &str = r#"  <entry>
+    <id>https://example.com/feed/74/001</id>
+    <title>001: security fix</title>
+    <updated>2024-01-02T00:00:00Z</updated>
+    <link href="https://www.openbsd.org/errata74.html#p001"/>
+    <content type="html">&lt;p&gt;fix&lt;/p&gt;</content>
+  </entry>
+"#;
+
+    fn feed(entry: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <id>https://example.com/feed</id>
+  <title>OpenBSD errata</title>
+  <updated>2024-01-02T00:00:00Z</updated>
+  <author><name>OpenBSD</name></author>
+{}</feed>
+"#,
+            entry
+        )
+    }
+
+    #[test]
+    fn accepts_valid_feed() {
+        assert_eq!(validate_feed(&feed(ENTRY)), Ok(()));
+    }
+
+    #[test]
+    fn reports_missing_id() {
+        let entry = ENTRY.replace("    <id>https://example.com/feed/74/001</id>\n", "");
+        assert_eq!(
+            validate_feed(&feed(&entry)),
+            Err(vec![String::from("7:3: <entry> is missing <id>")])
+        );
+    }
+
+    #[test]
+    fn reports_missing_updated() {
+        let entry = ENTRY.replace("    <updated>2024-01-02T00:00:00Z</updated>\n", "");
+        assert_eq!(
+            validate_feed(&feed(&entry)),
+            Err(vec![String::from("7:3: <entry> is missing <updated>")])
+        );
+    }
+
+    #[test]
+    fn reports_bad_timestamp_position() {
+        let entry = ENTRY.replace("2024-01-02T00:00:00Z", "2024-01-02 00:00");
+        let diagnostics = validate_feed(&feed(&entry)).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(
+            diagnostics[0].starts_with("10:5: \"2024-01-02 00:00\" is not an RFC 3339 date"),
+            "{}",
+            diagnostics[0]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_xml() {
+        let diagnostics = validate_feed("<feed>").unwrap_err();
+        assert!(diagnostics[0].starts_with("XML is not well-formed"));
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    atom_entry::AtomEntry,
+    config::{Config, WebhookConfig, WebhookKind},
+    credentials,
+    templates::{self, WEBHOOK_TEMPLATE},
+    ISO_UTC_FORMAT,
+};
+use chrono::Utc;
+use reqwest::{header, Client, RequestBuilder, StatusCode};
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime};
+use tera::Tera;
+use url::Url;
+
+/// Slack and most webhook URLs embed their secret, so only the host is logged.
+fn host(target: &WebhookConfig) -> String {
+    match Url::parse(&target.url) {
+        Ok(url) => format!("{}://{}", url.scheme(), url.host_str().unwrap_or_default()),
+        Err(_) => String::from("invalid URL"),
+    }
+}
+
+/// Lines shown under the title of an erratum.
+fn details(entry: &AtomEntry) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(ref impact) = entry.impact {
+        lines.push(format!("Impact: {}", impact.label()));
+    }
+    if let Some(ref cvss) = entry.cvss {
+        lines.push(format!("CVSS: {} ({})", cvss.score, cvss.severity));
+    }
+    if !entry.cves.is_empty() {
+        lines.push(format!("CVE: {}", entry.cves.join(", ")));
+    }
+    lines
+}
+
+/// Slack only wants these three escaped in `text`.
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn slack_body(entry: &AtomEntry) -> String {
+    let mut text = format!("<{}|{}>", entry.link, slack_escape(&entry.title));
+    for line in details(entry) {
+        text += &format!("\n{}", slack_escape(&line));
+    }
+    json!({ "text": text }).to_string()
+}
+
+fn matrix_body(entry: &AtomEntry) -> String {
+    let mut body = format!("{}\n{}", entry.title, entry.link);
+    let mut html = format!(
+        "<a href=\"{}\">{}</a>",
+        html_escape::encode_double_quoted_attribute(&entry.link),
+        html_escape::encode_text(&entry.title)
+    );
+    for line in details(entry) {
+        body += &format!("\n{}", line);
+        html += &format!("<br>{}", html_escape::encode_text(&line));
+    }
+    json!({
+        "msgtype": "m.notice",
+        "body": body,
+        "format": "org.matrix.custom.html",
+        "formatted_body": html,
+    })
+    .to_string()
+}
+
+/// The transaction id makes the homeserver ignore the retries of a message it already got.
+fn matrix_url(target: &WebhookConfig, entry: &AtomEntry) -> Result<Url, String> {
+    let room_id = target
+        .room_id
+        .as_deref()
+        .ok_or("a matrix webhook needs a room_id")?;
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let mut url = Url::parse(&target.url).map_err(|e| format!("invalid URL: {}", e))?;
+    url.path_segments_mut()
+        .map_err(|_| String::from("the URL can't be a homeserver"))?
+        .pop_if_empty()
+        .extend([
+            "_matrix",
+            "client",
+            "v3",
+            "rooms",
+            room_id,
+            "send",
+            "m.room.message",
+            &format!("syspatch-feed.{}.{}", nanos, entry.id),
+        ]);
+    Ok(url)
+}
+
+fn json_body(
+    config: &Config,
+    tera: &Tera,
+    target: &WebhookConfig,
+    entry: &AtomEntry,
+) -> Result<String, String> {
+    let updated = Utc::now().format(ISO_UTC_FORMAT).to_string();
+    let mut context = templates::feed_context(&config.feed, &updated, &[]);
+    context["entry"] = templates::entry_context(entry);
+    let template = target.template.as_deref().unwrap_or(WEBHOOK_TEMPLATE);
+    let body = templates::render(tera, template, &context)?;
+    //Better to catch a broken template here than as a 400 from the webhook
+    serde_json::from_str::<Value>(&body)
+        .map_err(|e| format!("{} didn't render valid JSON: {}", template, e))?;
+    Ok(body)
+}
+
+/// Sends the request built by `request`, again on network errors, 429 and 5xx, waiting twice
+/// as long each time.
+async fn send(request: impl Fn() -> RequestBuilder, retries: u32) -> Result<(), String> {
+    let mut attempt = 0;
+    loop {
+        let error = match request().send().await {
+            Ok(res) if res.status().is_success() => return Ok(()),
+            Ok(res) => {
+                let status = res.status();
+                let error = format!(
+                    "{}, Response Body: {:?}",
+                    status,
+                    res.text().await.unwrap_or_default()
+                );
+                if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                    return Err(error);
+                }
+                error
+            }
+            Err(e) => e.without_url().to_string(),
+        };
+        if attempt >= retries {
+            return Err(error);
+        }
+        attempt += 1;
+        let delay = 1 << attempt.min(6);
+        eprintln!(
+            "Webhook Error: {}, retrying in {}s ({}/{})",
+            error, delay, attempt, retries
+        );
+        tokio::time::sleep(Duration::from_secs(delay)).await;
+    }
+}
+
+async fn post(
+    config: &Config,
+    tera: &Tera,
+    target: &WebhookConfig,
+    token: Option<&str>,
+    entry: &AtomEntry,
+) -> Result<(), String> {
+    let client = Client::new();
+    let (url, body) = match target.kind {
+        WebhookKind::Slack => (
+            Url::parse(&target.url).map_err(|e| format!("invalid URL: {}", e))?,
+            slack_body(entry),
+        ),
+        WebhookKind::Matrix => (matrix_url(target, entry)?, matrix_body(entry)),
+        WebhookKind::Json => (
+            Url::parse(&target.url).map_err(|e| format!("invalid URL: {}", e))?,
+            json_body(config, tera, target, entry)?,
+        ),
+    };
+    let request = || {
+        let request = match target.kind {
+            WebhookKind::Matrix => client.put(url.clone()),
+            _ => client.post(url.clone()),
+        }
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body.clone());
+        match token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    };
+    send(request, target.retries).await
+}
+
+/// Posts the new entries to every webhook whose filters they match. A webhook that keeps
+/// failing gets no further entries this run.
+pub async fn notify(config: &Config, tera: &Tera, entries: &[&AtomEntry]) {
+    for target in &config.webhooks {
+        let matching: Vec<&AtomEntry> = entries
+            .iter()
+            .copied()
+            .filter(|e| e.matches(&target.releases, &target.kinds))
+            .collect();
+        if matching.is_empty() {
+            continue;
+        }
+        let token = match target.token {
+            Some(ref token) => match credentials::resolve(token).await {
+                Ok(token) => Some(token),
+                Err(e) => {
+                    eprintln!("Webhook Error: {}: Credential Error: {}", host(target), e);
+                    continue;
+                }
+            },
+            None if matches!(target.kind, WebhookKind::Matrix) => {
+                eprintln!(
+                    "Webhook Error: {}: a matrix webhook needs a token",
+                    host(target)
+                );
+                continue;
+            }
+            None => None,
+        };
+        for entry in matching {
+            match post(config, tera, target, token.as_deref(), entry).await {
+                Ok(()) => println!("Posted {} to {}", entry.id, host(target)),
+                Err(e) => {
+                    eprintln!("Webhook Error: {}: {}", host(target), e);
+                    break;
+                }
+            }
+        }
+    }
+}
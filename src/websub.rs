@@ -0,0 +1,205 @@
+use crate::{
+    config::FeedConfig,
+    crypto::{self, hex, hmac_sha256},
+    ATOM_CONTENT_TYPE, GITHUB_REPO_OWNER,
+};
+use chrono::{DateTime, Duration, Utc};
+use hyper::{header, Body, Request, Response, StatusCode};
+use reqwest::Client;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use url::{form_urlencoded, Url};
+
+const DEFAULT_LEASE_SECS: i64 = 10 * 24 * 3600;
+
+/// Tells a hub that `topic` changed, so it fetches and distributes it.
+pub async fn ping(hub: &str, topic: &str) -> Result<(), String> {
+    let res = Client::new()
+        .post(hub)
+        .header(reqwest::header::USER_AGENT, GITHUB_REPO_OWNER)
+        .form(&[("hub.mode", "publish"), ("hub.url", topic)])
+        .send()
+        .await
+        .map_err(|e| format!("request to {} failed: {}", hub, e))?;
+    match res.status().as_u16() {
+        200..=299 => Ok(()),
+        status => Err(format!(
+            "Hub Error: {}, Response Body: {:?}",
+            status,
+            res.text().await.unwrap_or_default()
+        )),
+    }
+}
+
+/// Pings the hub of the feed, if any.
+pub async fn notify(feed: &FeedConfig) {
+    if let Some(ref hub) = feed.hub {
+        match ping(hub, &feed.link).await {
+            Ok(()) => println!("Notified WebSub hub {}", hub),
+            Err(e) => eprintln!("WebSub Error: {}", e),
+        }
+    }
+}
+
+struct Subscription {
+    expires: DateTime<Utc>,
+    secret: Option<String>,
+}
+
+/// Minimal WebSub hub for the feed alone. Subscriptions live in memory, subscribers renew
+/// them anyway before their lease ends.
+pub struct Hub {
+    url: String,
+    topic: String,
+    subscriptions: Mutex<HashMap<String, Subscription>>,
+}
+
+fn response(status: StatusCode, body: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(body.to_string()))
+        .unwrap_or_default()
+}
+
+impl Hub {
+    pub fn new(url: &str, topic: &str) -> Hub {
+        Hub {
+            url: url.to_string(),
+            topic: topic.to_string(),
+            subscriptions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a subscription request and verifies it in the background, as the spec asks.
+    pub async fn handle(self: Arc<Self>, req: Request<Body>) -> Response<Body> {
+        let body = match hyper::body::to_bytes(req.into_body()).await {
+            Ok(body) => body,
+            Err(e) => return response(StatusCode::BAD_REQUEST, &e.to_string()),
+        };
+        let params: HashMap<String, String> = form_urlencoded::parse(&body).into_owned().collect();
+        let param = |name: &str| params.get(name).map(String::as_str);
+
+        let subscribe = match param("hub.mode") {
+            Some("subscribe") => true,
+            Some("unsubscribe") => false,
+            _ => return response(StatusCode::BAD_REQUEST, "unsupported hub.mode"),
+        };
+        if param("hub.topic") != Some(self.topic.as_str()) {
+            return response(StatusCode::NOT_FOUND, "unknown hub.topic");
+        }
+        let callback = match param("hub.callback").map(Url::parse) {
+            Some(Ok(url)) if url.scheme() == "http" || url.scheme() == "https" => url,
+            _ => return response(StatusCode::BAD_REQUEST, "invalid hub.callback"),
+        };
+        let lease = param("hub.lease_seconds")
+            .and_then(|l| l.parse::<i64>().ok())
+            .map_or(DEFAULT_LEASE_SECS, |l| l.clamp(60, DEFAULT_LEASE_SECS));
+        let secret = param("hub.secret").map(String::from);
+
+        tokio::spawn(async move {
+            match self.verify(&callback, subscribe, lease).await {
+                Ok(()) => {
+                    let mut subscriptions =
+                        self.subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+                    match subscribe {
+                        true => {
+                            println!("WebSub: {} subscribed for {}s", callback, lease);
+                            subscriptions.insert(
+                                callback.to_string(),
+                                Subscription {
+                                    expires: Utc::now() + Duration::seconds(lease),
+                                    secret,
+                                },
+                            );
+                        }
+                        false => {
+                            println!("WebSub: {} unsubscribed", callback);
+                            subscriptions.remove(callback.as_str());
+                        }
+                    }
+                }
+                Err(e) => eprintln!("WebSub: cannot verify {}: {}", callback, e),
+            }
+        });
+        response(StatusCode::ACCEPTED, "")
+    }
+
+    /// The subscriber has to echo the challenge back.
+    async fn verify(&self, callback: &Url, subscribe: bool, lease: i64) -> Result<(), String> {
+        let challenge = crypto::random_hex(32)?;
+        let mut url = callback.clone();
+        url.query_pairs_mut()
+            .append_pair(
+                "hub.mode",
+                if subscribe {
+                    "subscribe"
+                } else {
+                    "unsubscribe"
+                },
+            )
+            .append_pair("hub.topic", &self.topic)
+            .append_pair("hub.challenge", &challenge)
+            .append_pair("hub.lease_seconds", &lease.to_string());
+        let res = Client::new()
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            return Err(format!("callback answered {}", res.status()));
+        }
+        match res.text().await {
+            Ok(body) if body.trim() == challenge => Ok(()),
+            Ok(_) => Err(String::from("wrong challenge echoed")),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Sends the new feed to every subscriber, signed with its secret when it gave one.
+    pub async fn distribute(&self, feed: &str) {
+        let subscribers: Vec<(String, Option<String>)> = {
+            let mut subscriptions = self.subscriptions.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Utc::now();
+            subscriptions.retain(|_, s| s.expires > now);
+            subscriptions
+                .iter()
+                .map(|(callback, s)| (callback.clone(), s.secret.clone()))
+                .collect()
+        };
+        let link = format!(
+            "<{}>; rel=\"hub\", <{}>; rel=\"self\"",
+            self.url, self.topic
+        );
+        for (callback, secret) in subscribers {
+            let mut request = Client::new()
+                .post(&callback)
+                .header(reqwest::header::CONTENT_TYPE, ATOM_CONTENT_TYPE)
+                .header(reqwest::header::LINK, &link)
+                .body(feed.to_string());
+            if let Some(secret) = secret {
+                request = request.header(
+                    "X-Hub-Signature",
+                    format!("sha256={}", hex(&hmac_sha256(secret.as_bytes(), feed))),
+                );
+            }
+            match request.send().await {
+                Ok(res) if res.status().is_success() => {
+                    println!("WebSub: delivered to {}", callback)
+                }
+                Ok(res) => eprintln!("WebSub: {} answered {}", callback, res.status()),
+                Err(e) => eprintln!("WebSub: cannot deliver to {}: {}", callback, e),
+            }
+        }
+    }
+}
+
+/// Only POST makes sense on the hub endpoint.
+pub fn method_not_allowed() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::METHOD_NOT_ALLOWED)
+        .header(header::ALLOW, "POST")
+        .body(Body::empty())
+        .unwrap_or_default()
+}